@@ -0,0 +1,234 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::observer::{CopyEvent, CopyObserver};
+use crate::options::CopyOptions;
+use crate::stats::Statistics;
+
+/// Where one run of a job keeps the destination files it deletes or overwrites (/BACKUPDIR)
+///
+/// Every run gets its own `<backup dir>/<timestamp>` tree, in which a file keeps its path
+/// relative to the destination.
+#[derive(Debug)]
+pub(crate) struct Backup {
+    dir: PathBuf,
+    dest_root: PathBuf,
+    session: PathBuf,
+}
+
+impl Backup {
+    pub(crate) fn new(dir: &Path, dest_root: &Path, started: SystemTime) -> Self {
+        // Runs started within the same second must not overwrite each other's backups
+        let name = session_name(started);
+        let mut session = dir.join(&name);
+        let mut run = 1;
+        while session.exists() {
+            run += 1;
+            session = dir.join(format!("{}-{}", name, run));
+        }
+
+        Backup { dir: dir.to_path_buf(), dest_root: dest_root.to_path_buf(), session }
+    }
+
+    /// Move the destination file at `path` into the backup tree before it is deleted or overwritten
+    pub(crate) fn save(&self, path: &Path, observer: &dyn CopyObserver, stats: &Mutex<Statistics>) -> io::Result<()> {
+        let backup = self.move_to_backup(path)?;
+        observer.event(&CopyEvent::BackedUp { path, backup: &backup });
+        stats.lock().unwrap().files_backed_up += 1;
+        Ok(())
+    }
+
+    fn move_to_backup(&self, path: &Path) -> io::Result<PathBuf> {
+        let relative = path.strip_prefix(&self.dest_root).unwrap_or(path);
+        let target = self.session.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match fs::rename(path, &target) {
+            Ok(_) => {}
+            // The backup directory is on another file system, so the file has to be copied
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                fs::copy(path, &target)?;
+                fs::remove_file(path)?;
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(target)
+    }
+
+    /// Whether `path` is the backup directory itself, which happens when it is inside the
+    /// destination; purging must leave it alone
+    pub(crate) fn is_backup_dir(&self, path: &Path) -> bool {
+        path.file_name() == self.dir.file_name()
+            && path.canonicalize().is_ok_and(|path| self.dir.canonicalize().is_ok_and(|dir| dir == path))
+    }
+
+    /// Delete the backups of earlier runs that /BACKUPKEEP or /BACKUPDAYS no longer keep
+    pub(crate) fn prune(&self, options: &CopyOptions, observer: &dyn CopyObserver) -> io::Result<()> {
+        if options.backup_versions == 0 && options.backup_days == 0 {
+            return Ok(());
+        }
+
+        // Nothing was ever backed up, so there is nothing to prune either
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let mut sessions = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Anything else in the backup directory was not put there by us
+            if is_session_name(&name) && entry.file_type()?.is_dir() {
+                sessions.push(name);
+            }
+        }
+
+        // Session names sort chronologically, newest last
+        sessions.sort_by(|a, b| session_order(a).cmp(&session_order(b)));
+        let keep_from = if options.backup_versions > 0 {
+            sessions.len().saturating_sub(options.backup_versions)
+        } else {
+            0
+        };
+        let cutoff = (options.backup_days > 0).then(|| {
+            let age = Duration::from_secs(options.backup_days * 24 * 60 * 60);
+            session_name(SystemTime::now().checked_sub(age).unwrap_or(UNIX_EPOCH))
+        });
+
+        let current = self.session.file_name().unwrap_or_default().to_string_lossy();
+        for (index, name) in sessions.iter().enumerate() {
+            let expired = index < keep_from || cutoff.as_ref().is_some_and(|cutoff| name < cutoff);
+            if !expired || *name == current {
+                continue;
+            }
+
+            let path = self.dir.join(name);
+            observer.event(&CopyEvent::Notice { message: &format!("Removing old backup: {}", path.display()) });
+            fs::remove_dir_all(&path)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Directory name of the backups made by a run started at `time`, like `2024-05-17_142301`
+fn session_name(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0)).as_secs();
+    let (days, remainder) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_date(days);
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        remainder / 3600,
+        remainder % 3600 / 60,
+        remainder % 60
+    )
+}
+
+/// Whether `name` is a session directory, `2024-05-17_142301` or, for a later run in the same
+/// second, `2024-05-17_142301-2`
+fn is_session_name(name: &str) -> bool {
+    // Other names in the backup directory may be shorter, or have a multibyte character there
+    let Some((timestamp, run)) = name.split_at_checked(17) else {
+        return false;
+    };
+    let timestamp_valid = timestamp.char_indices().all(|(i, c)| match i {
+        4 | 7 => c == '-',
+        10 => c == '_',
+        _ => c.is_ascii_digit(),
+    });
+    timestamp_valid && (run.is_empty() || run.strip_prefix('-').is_some_and(|run| run.parse::<u32>().is_ok()))
+}
+
+/// Sort key of a session directory: its timestamp, then its run within that second
+fn session_order(name: &str) -> (&str, u32) {
+    let (timestamp, run) = name.split_at(17);
+    (timestamp, run.strip_prefix('-').and_then(|run| run.parse().ok()).unwrap_or(1))
+}
+
+/// Year, month and day of the given number of days since 1970-01-01, in the Gregorian calendar
+fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Counted from 0000-03-01, so that the leap day is the last day of the year
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_date_counts_from_the_epoch() {
+        assert_eq!(civil_date(0), (1970, 1, 1));
+        assert_eq!(civil_date(19_860), (2024, 5, 17));
+        assert_eq!(civil_date(10_956), (1999, 12, 31));
+    }
+
+    #[test]
+    fn civil_date_handles_leap_days() {
+        assert_eq!(civil_date(11_016), (2000, 2, 29));
+        assert_eq!(civil_date(11_017), (2000, 3, 1));
+        // 2100 is not a leap year, February 28 is followed by March 1
+        assert_eq!(civil_date(47_540), (2100, 2, 28));
+        assert_eq!(civil_date(47_541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn session_name_is_the_utc_timestamp() {
+        let started = UNIX_EPOCH + Duration::from_secs(1_715_955_781);
+        assert_eq!(session_name(started), "2024-05-17_142301");
+        assert_eq!(session_name(UNIX_EPOCH), "1970-01-01_000000");
+    }
+
+    #[test]
+    fn session_names_are_recognized() {
+        assert!(is_session_name("2024-05-17_142301"));
+        assert!(is_session_name("2024-05-17_142301-2"));
+        assert!(is_session_name("2024-05-17_142301-10"));
+    }
+
+    #[test]
+    fn other_names_are_not_sessions() {
+        for name in [
+            "",
+            "notes.txt",
+            "2024-05-17",
+            "2024-05-17_14230",
+            "2024-05-17_1423011",
+            "2024-05-17 142301",
+            "2024_05-17_142301",
+            "2024-05-17_142301-",
+            "2024-05-17_142301-x",
+            "2024-05-17_142301_2",
+            "2024-05-17_14230é",
+        ] {
+            assert!(!is_session_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn later_runs_of_a_second_sort_after_the_first() {
+        let mut sessions = ["2024-05-17_142301-10", "2024-05-17_142302", "2024-05-17_142301-2", "2024-05-17_142301"];
+        sessions.sort_by(|a, b| session_order(a).cmp(&session_order(b)));
+        assert_eq!(sessions, ["2024-05-17_142301", "2024-05-17_142301-2", "2024-05-17_142301-10", "2024-05-17_142302"]);
+    }
+}
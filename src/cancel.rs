@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a cancellable wait checks whether the job was cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stops a running [`CopyJob`](crate::CopyJob) from another thread
+///
+/// Once cancelled, the job stops handing out new files, aborts the files being copied and
+/// returns its statistics with [`Statistics::interrupted`](crate::Statistics::interrupted) set.
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every job using this token to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// The flag behind the token, e.g. for setting it from a signal handler
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.cancelled)
+    }
+
+    /// Sleep for `duration`, waking up early if the token is cancelled
+    pub(crate) fn sleep(&self, duration: Duration) {
+        // A wait too long for an Instant only ends with the job
+        let deadline = Instant::now().checked_add(duration);
+        while !self.is_cancelled() {
+            let remaining =
+                deadline.map_or(POLL_INTERVAL, |deadline| deadline.saturating_duration_since(Instant::now()));
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(POLL_INTERVAL));
+        }
+    }
+}
+
+/// Error payload of a copy that was aborted because the job was cancelled
+#[derive(Debug)]
+struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The copy was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+pub(crate) fn cancelled_error() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, Cancelled)
+}
+
+/// Whether `e` comes from a cancelled copy rather than from the file system
+pub(crate) fn is_cancelled_error(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<Cancelled>())
+}
@@ -0,0 +1,79 @@
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Suffix of the sidecar file that records how far a restartable copy got
+const CHECKPOINT_SUFFIX: &str = ".colemen_partial";
+
+/// Number of bytes written between two checkpoints in restartable mode
+pub(crate) const CHECKPOINT_INTERVAL: u64 = 16 * 1024 * 1024;
+
+/// Progress of a restartable copy, stored next to the destination file
+pub(crate) struct Checkpoint {
+    source_size: u64,
+    source_mtime: Duration,
+    pub(crate) bytes_committed: u64,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(src_meta: &Metadata, bytes_committed: u64) -> Self {
+        Checkpoint {
+            source_size: src_meta.len(),
+            source_mtime: modified_since_epoch(src_meta),
+            bytes_committed,
+        }
+    }
+
+    pub(crate) fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        let mut fields = contents.split_whitespace().map(|field| field.parse::<u64>());
+
+        let source_size = fields.next()?.ok()?;
+        let mtime_secs = fields.next()?.ok()?;
+        let mtime_nanos = fields.next()?.ok()?;
+        let bytes_committed = fields.next()?.ok()?;
+
+        Some(Checkpoint {
+            source_size,
+            source_mtime: Duration::new(mtime_secs, mtime_nanos as u32),
+            bytes_committed,
+        })
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(
+            path,
+            format!(
+                "{} {} {} {}\n",
+                self.source_size,
+                self.source_mtime.as_secs(),
+                self.source_mtime.subsec_nanos(),
+                self.bytes_committed
+            ),
+        )
+    }
+
+    /// A checkpoint is only usable if the source has not changed since it was written
+    pub(crate) fn matches(&self, src_meta: &Metadata) -> bool {
+        self.source_size == src_meta.len() && self.source_mtime == modified_since_epoch(src_meta)
+    }
+}
+
+fn modified_since_epoch(meta: &Metadata) -> Duration {
+    meta.modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or(Duration::from_secs(0))
+}
+
+/// Hidden sidecar path used to checkpoint a restartable copy of `dst_path`
+pub(crate) fn checkpoint_path(dst_path: &Path) -> PathBuf {
+    let file_name = dst_path.file_name().unwrap_or_default().to_string_lossy();
+    dst_path.with_file_name(format!(".{}{}", file_name, CHECKPOINT_SUFFIX))
+}
+
+/// Returns the destination file name a checkpoint sidecar belongs to, if `file_name` is one
+pub(crate) fn checkpoint_target(file_name: &str) -> Option<&str> {
+    file_name.strip_prefix('.')?.strip_suffix(CHECKPOINT_SUFFIX)
+}
@@ -0,0 +1,79 @@
+use std::cmp::Ordering;
+use std::fs::Metadata;
+use std::time::SystemTime;
+
+use crate::options::CopyOptions;
+
+/// How a file relates to the file of the same name on the other side, Robocopy's file classes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileClass {
+    /// The file only exists in the source
+    Lonely,
+    /// The source file is newer than the destination file
+    Newer,
+    /// The source file is older than the destination file
+    Older,
+    /// Both have the same timestamp but different sizes, or different contents with /CHECKSUM
+    Changed,
+    /// Both have the same timestamp, size and permissions
+    Same,
+    /// Both have the same timestamp and size, but different permissions
+    Tweaked,
+    /// The file only exists in the destination
+    Extra,
+}
+
+impl FileClass {
+    /// Tag of the class in the log, the one Robocopy uses
+    pub fn label(self) -> &'static str {
+        match self {
+            FileClass::Lonely => "New File",
+            FileClass::Newer => "Newer",
+            FileClass::Older => "Older",
+            FileClass::Changed => "Changed",
+            FileClass::Same => "same",
+            FileClass::Tweaked => "Tweaked",
+            FileClass::Extra => "*EXTRA File",
+        }
+    }
+
+    /// Whether files of this class are copied; by default Lonely, Newer and Changed files are,
+    /// /XL, /XN and /XC leave them out and /IO, /IS and /IT add Older, Same and Tweaked files
+    pub(crate) fn is_copied(self, options: &CopyOptions) -> bool {
+        match self {
+            FileClass::Lonely => !options.exclude_lonely,
+            FileClass::Newer => !options.exclude_newer,
+            // A newer destination file is never overwritten unless asked for
+            FileClass::Older => options.include_older && !options.exclude_older,
+            FileClass::Changed => !options.exclude_changed,
+            FileClass::Same => options.include_same,
+            FileClass::Tweaked => options.include_tweaked,
+            FileClass::Extra => false,
+        }
+    }
+}
+
+/// Class of a source file, given the destination file of the same name if there is one
+pub(crate) fn classify(src_meta: &Metadata, dst_meta: Option<&Metadata>) -> FileClass {
+    let Some(dst_meta) = dst_meta else {
+        return FileClass::Lonely;
+    };
+
+    let src_modified = src_meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let dst_modified = dst_meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    match src_modified.cmp(&dst_modified) {
+        Ordering::Greater => FileClass::Newer,
+        Ordering::Less => FileClass::Older,
+        Ordering::Equal if src_meta.len() != dst_meta.len() => FileClass::Changed,
+        Ordering::Equal => same_or_tweaked(src_meta, dst_meta),
+    }
+}
+
+/// Class of two files that have the same contents, which only their permissions tell apart
+pub(crate) fn same_or_tweaked(src_meta: &Metadata, dst_meta: &Metadata) -> FileClass {
+    if src_meta.permissions() == dst_meta.permissions() {
+        FileClass::Same
+    } else {
+        FileClass::Tweaked
+    }
+}
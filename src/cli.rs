@@ -0,0 +1,712 @@
+//! Command line parsing
+//!
+//! Every option has a Robocopy style spelling (`/MIR`, `/MT:16`) and GNU style ones
+//! (`--mirror`, `--threads=16`, `-m`). Options may appear anywhere, `--` ends them.
+
+use std::fmt;
+use std::str::FromStr;
+
+use colemen_copy::{ChecksumAlgorithm, CopyOptions, CopyOptionsBuilder, DeleteLimit};
+
+/// Whether an option takes a value
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Value {
+    None,
+    /// The value must be given, the string names it in the help text
+    Required(&'static str),
+    /// The value may be left out, e.g. `/MT` or `--threads`
+    Optional(&'static str),
+    /// One or more values; the Robocopy spelling takes every following argument up to the next
+    /// option, the GNU one takes a single value and can be repeated
+    List(&'static str),
+}
+
+struct OptionSpec {
+    /// Robocopy style name, without the `:` that introduces a value
+    name: &'static str,
+    long: &'static str,
+    short: Option<char>,
+    value: Value,
+    help: &'static str,
+}
+
+const OPTIONS: &[OptionSpec] = &[
+    OptionSpec { name: "/S", long: "subdirs", short: Some('s'), value: Value::None, help: "Copy subdirectories, but not empty ones" },
+    OptionSpec { name: "/E", long: "empty-dirs", short: Some('e'), value: Value::None, help: "Copy subdirectories, including empty ones" },
+    OptionSpec { name: "/Z", long: "restartable", short: Some('z'), value: Value::None, help: "Copy files in restartable mode (slower but more robust)" },
+    OptionSpec { name: "/B", long: "backup", short: Some('b'), value: Value::None, help: "Copy files in Backup mode (overrides permissions)" },
+    OptionSpec { name: "/PURGE", long: "purge", short: None, value: Value::None, help: "Delete destination files/folders that no longer exist in source" },
+    OptionSpec { name: "/PURGEALL", long: "purge-all", short: None, value: Value::None, help: "Like /PURGE, but also delete destination files that do not match the file patterns" },
+    OptionSpec { name: "/MAXDEL", long: "max-delete", short: None, value: Value::Required("n[%]"), help: "Refuse to purge if more than n entries (or n percent) of the destination would be deleted" },
+    OptionSpec { name: "/FORCE", long: "force", short: None, value: Value::None, help: "Purge even from an empty source, or onto /, home or a directory never mirrored to" },
+    OptionSpec { name: "/BACKUPDIR", long: "backup-dir", short: None, value: Value::Required("dir"), help: "Move deleted and overwritten destination files into a timestamped backup tree in dir" },
+    OptionSpec { name: "/BACKUPKEEP", long: "backup-keep", short: None, value: Value::Required("n"), help: "Only keep the backups of the last n runs (default is all)" },
+    OptionSpec { name: "/BACKUPDAYS", long: "backup-days", short: None, value: Value::Required("n"), help: "Delete backups older than n days (default is never)" },
+    OptionSpec { name: "/MIR", long: "mirror", short: Some('m'), value: Value::None, help: "Mirror directory tree (like /PURGE plus all subdirectories)" },
+    OptionSpec { name: "/MOV", long: "move-files", short: None, value: Value::None, help: "Move files (delete from source after copying)" },
+    OptionSpec { name: "/MOVE", long: "move", short: None, value: Value::None, help: "Move files and directories (delete from source after copying)" },
+    OptionSpec { name: "/VERIFYMOVE", long: "verify-move", short: None, value: Value::None, help: "Only delete a moved source file once the copy has the same SHA-256" },
+    OptionSpec { name: "/A+", long: "add-attributes", short: None, value: Value::Required("RASHCNETO"), help: "Add specified attributes to copied files" },
+    OptionSpec { name: "/A-", long: "remove-attributes", short: None, value: Value::Required("RASHCNETO"), help: "Remove specified attributes from copied files" },
+    OptionSpec { name: "/MT", long: "threads", short: None, value: Value::Optional("n"), help: "Multithreaded copying with n threads (default is 8)" },
+    OptionSpec { name: "/R", long: "retries", short: Some('r'), value: Value::Required("n"), help: "Number of retries on failed copies (default is 1 million)" },
+    OptionSpec { name: "/W", long: "wait", short: Some('w'), value: Value::Required("n"), help: "Wait time between retries in seconds (default is 30)" },
+    OptionSpec { name: "/BACKOFF", long: "backoff", short: None, value: Value::Required("n"), help: "Multiply the wait time by n after every retry (default is 2)" },
+    OptionSpec { name: "/WMAX", long: "max-wait", short: None, value: Value::Required("n"), help: "Maximum wait between two retries in seconds (default is 600)" },
+    OptionSpec { name: "/WTOTAL", long: "total-wait", short: None, value: Value::Required("n"), help: "Give up on a file after waiting n seconds in total (default is no limit)" },
+    OptionSpec { name: "/JITTER", long: "jitter", short: None, value: Value::Required("n"), help: "Randomly vary each wait by up to n percent (default is 20)" },
+    OptionSpec { name: "/XF", long: "exclude-file", short: None, value: Value::List("pattern"), help: "Exclude files matching the patterns (names or paths relative to the source)" },
+    OptionSpec { name: "/XD", long: "exclude-dir", short: None, value: Value::List("pattern"), help: "Exclude directories matching the patterns (names or paths relative to the source)" },
+    OptionSpec { name: "/XO", long: "exclude-older", short: None, value: Value::None, help: "Exclude older files, never overwrite a newer destination file (the default)" },
+    OptionSpec { name: "/IO", long: "include-older", short: None, value: Value::None, help: "Include older files, overwrite destination files that are newer than the source" },
+    OptionSpec { name: "/XN", long: "exclude-newer", short: None, value: Value::None, help: "Exclude newer files" },
+    OptionSpec { name: "/XC", long: "exclude-changed", short: None, value: Value::None, help: "Exclude changed files (same timestamp, different size)" },
+    OptionSpec { name: "/XX", long: "exclude-extra", short: None, value: Value::None, help: "Exclude extra files and directories, which are neither reported nor purged" },
+    OptionSpec { name: "/XL", long: "exclude-lonely", short: None, value: Value::None, help: "Exclude lonely files and directories, only update what the destination already has" },
+    OptionSpec { name: "/IS", long: "include-same", short: None, value: Value::None, help: "Include same files, copy them even though nothing changed" },
+    OptionSpec { name: "/IT", long: "include-tweaked", short: None, value: Value::None, help: "Include tweaked files (same timestamp and size, different permissions)" },
+    OptionSpec { name: "/IGNOREFILES", long: "ignore-files", short: None, value: Value::None, help: "Leave out what .gitignore, .ignore and .colemenignore files list" },
+    OptionSpec { name: "/LOG", long: "log", short: None, value: Value::Required("file"), help: "Output log to file" },
+    OptionSpec { name: "/L", long: "list", short: Some('l'), value: Value::None, help: "List only - don't copy, timestamp or delete any files" },
+    OptionSpec { name: "/NP", long: "no-progress", short: None, value: Value::None, help: "No progress - don't display % copied" },
+    OptionSpec { name: "/NFL", long: "no-file-list", short: None, value: Value::None, help: "No file list - don't log file names" },
+    OptionSpec { name: "/EMPTY", long: "empty-files", short: None, value: Value::None, help: "Create empty (zero-byte) copies of files" },
+    OptionSpec { name: "/CHILDONLY", long: "child-only", short: None, value: Value::None, help: "Process only direct child folders of source path" },
+    OptionSpec { name: "/SHRED", long: "shred", short: None, value: Value::None, help: "Securely overwrite files before deletion" },
+    OptionSpec { name: "/ATOMIC", long: "atomic", short: None, value: Value::None, help: "Write each file to a temporary name and rename it into place" },
+    OptionSpec { name: "/REFLINK", long: "reflink", short: None, value: Value::None, help: "Share the data of copies with the source on copy-on-write file systems (btrfs, XFS)" },
+    OptionSpec { name: "/SPARSE", long: "sparse", short: None, value: Value::None, help: "Turn blocks of zeros into holes in the destination (holes of sparse sources are always kept)" },
+    OptionSpec { name: "/V", long: "verify", short: None, value: Value::None, help: "Read every copy back from the destination and copy it again if it differs from the source" },
+    OptionSpec { name: "/CHECKSUM", long: "checksum", short: None, value: Value::Optional("XXH3|SHA256"), help: "Copy files of the same size only if their contents differ, ignoring timestamps (default hash is XXH3)" },
+    OptionSpec { name: "/?", long: "help", short: Some('h'), value: Value::None, help: "Show this help" },
+];
+
+/// The copy job a command line asks for
+pub struct Arguments {
+    pub source: String,
+    pub destination: String,
+    pub file_patterns: Vec<String>,
+    pub options: CopyOptions,
+}
+
+/// A command line that cannot be run
+#[derive(Debug)]
+pub struct UsageError(String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn error<T>(message: String) -> Result<T, UsageError> {
+    Err(UsageError(message))
+}
+
+/// Parse the arguments, without the program name; `None` means the help was asked for
+pub fn parse_args(args: &[String]) -> Result<Option<Arguments>, UsageError> {
+    let mut positional = Vec::new();
+    let mut options = CopyOptions::builder();
+    let mut help = false;
+    let mut args = args.iter().peekable();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref().cloned());
+            break;
+        }
+
+        let (spec, value, display) = if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let Some(spec) = OPTIONS.iter().find(|spec| spec.long == name) else {
+                return error(format!("Unknown option: --{}", name));
+            };
+
+            // A required value may also be the next argument, an optional one only if it is a valid value
+            let value = match (spec.value, value) {
+                (Value::Required(_) | Value::List(_), None) => args.next().cloned(),
+                (Value::Optional(_), None) => args.next_if(|next| is_optional_value(spec, next)).cloned(),
+                (_, value) => value,
+            };
+            (spec, value, format!("--{}", name))
+        } else if let Some(shorts) = arg.strip_prefix('-')
+            && !shorts.is_empty()
+        {
+            // Short options can be grouped (`-se`); one taking a value ends the group
+            let mut last = None;
+            for (index, c) in shorts.char_indices() {
+                let Some(spec) = OPTIONS.iter().find(|spec| spec.short == Some(c)) else {
+                    return error(format!("Unknown option: -{}", c));
+                };
+                if spec.value == Value::None {
+                    if spec.name == "/?" {
+                        help = true;
+                    } else {
+                        apply(&mut options, spec, &format!("-{}", c), None)?;
+                    }
+                    continue;
+                }
+
+                let rest = &shorts[index + c.len_utf8()..];
+                let value = if rest.is_empty() { args.next().cloned() } else { Some(rest.to_string()) };
+                last = Some((spec, value, format!("-{}", c)));
+                break;
+            }
+            match last {
+                Some(last) => last,
+                None => continue,
+            }
+        } else if let Some((spec, value)) = find_slash_option(arg) {
+            if let Value::List(name) = spec.value
+                && value.is_none()
+            {
+                let mut count = 0;
+                while let Some(item) = args.next_if(|next| !is_option(next)) {
+                    apply(&mut options, spec, spec.name, Some(item))?;
+                    count += 1;
+                }
+                if count == 0 {
+                    return error(format!("Option {} requires at least one value ({})", spec.name, name));
+                }
+                continue;
+            }
+            (spec, value, spec.name.to_string())
+        } else if looks_like_option(arg) {
+            let name = arg.split(':').next().unwrap_or_default();
+            return match suggest(name) {
+                Some(spec) => error(format!("Unknown option: {} (did you mean {}?)", name, spec.name)),
+                None => error(format!("Unknown option: {} (put -- before paths that look like options)", name)),
+            };
+        } else {
+            // Anything else is a path or a pattern, including absolute paths like /data/*.log
+            positional.push(arg.clone());
+            continue;
+        };
+
+        match (spec.value, &value) {
+            (Value::None, Some(_)) => return error(format!("Option {} does not take a value", display)),
+            (Value::Required(name) | Value::List(name), None) => {
+                return error(format!("Option {} requires a value ({})", display, name));
+            }
+            _ => {}
+        }
+
+        if spec.name == "/?" {
+            help = true;
+        } else {
+            apply(&mut options, spec, &display, value.as_deref())?;
+        }
+    }
+
+    if help {
+        return Ok(None);
+    }
+
+    let mut positional = positional.into_iter();
+    let (Some(source), Some(destination)) = (positional.next(), positional.next()) else {
+        return error("Missing source or destination directory".to_string());
+    };
+    let file_patterns = positional.collect();
+
+    // Contradictory combinations are refused before anything is touched
+    let options = options.build();
+    if let Err(e) = options.validate() {
+        return error(e.to_string());
+    }
+
+    Ok(Some(Arguments { source, destination, file_patterns, options }))
+}
+
+/// Look up a Robocopy style option like `/MIR` or `/MT:16`, ignoring case
+fn find_slash_option(arg: &str) -> Option<(&'static OptionSpec, Option<String>)> {
+    if !arg.starts_with('/') {
+        return None;
+    }
+
+    let (name, value) = match arg.split_once(':') {
+        Some((name, value)) => (name, Some(value.to_string())),
+        None => (arg, None),
+    };
+    let spec = OPTIONS.iter().find(|spec| spec.name.eq_ignore_ascii_case(name))?;
+    Some((spec, value))
+}
+
+/// Whether an argument is an option, `--`, or a mistyped option, which ends the values of /XF and /XD
+fn is_option(arg: &str) -> bool {
+    (arg.starts_with('-') && arg.len() > 1) || find_slash_option(arg).is_some() || looks_like_option(arg)
+}
+
+/// Whether an argument that is not a known option is a mistyped option rather than a path
+///
+/// `/PURGUE`, `/MT16` or `/MTX:4` look like options, `/data/backup` or `/newdest` do not: a single
+/// word after the slash only counts as an option if it is close to one or has a value.
+fn looks_like_option(arg: &str) -> bool {
+    let Some(name) = arg.strip_prefix('/') else {
+        return false;
+    };
+    let (name, value) = match name.split_once(':') {
+        Some((name, _)) => (name, true),
+        None => (name, false),
+    };
+
+    !name.is_empty()
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '?'))
+        // Without a value, an option that needs one could not have been meant either
+        && (value || suggest(&format!("/{}", name)).is_some_and(|spec| !matches!(spec.value, Value::Required(_))))
+}
+
+/// The known option a mistyped one was most likely meant to be
+///
+/// Only close matches count, so that short paths like `/mnt` or `/srv` are not taken for `/MT`
+/// or `/S`: an option followed by digits, one typo in an option of three letters or more, or two
+/// typos in one of seven letters or more.
+fn suggest(name: &str) -> Option<&'static OptionSpec> {
+    let name = name.to_ascii_uppercase();
+
+    // `/MT16` is `/MT:16` without the colon
+    let prefixed = OPTIONS
+        .iter()
+        .filter(|spec| {
+            name.strip_prefix(spec.name)
+                .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit()))
+        })
+        .max_by_key(|spec| spec.name.len());
+
+    prefixed.or_else(|| {
+        OPTIONS
+            .iter()
+            .filter(|spec| spec.name.len() > 3)
+            .map(|spec| (edit_distance(&name, spec.name), spec))
+            .filter(|(distance, spec)| *distance <= if spec.name.len() > 7 { 2 } else { 1 })
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, spec)| spec)
+    })
+}
+
+/// Number of single character insertions, deletions and substitutions turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Whether `arg` is a value of `spec`, whose value may be left out; `--threads 4` takes the 4,
+/// `--checksum photos` leaves `photos` as a path
+fn is_optional_value(spec: &OptionSpec, arg: &str) -> bool {
+    match spec.name {
+        "/MT" => arg.parse::<usize>().is_ok(),
+        "/CHECKSUM" => checksum_algorithm(arg).is_some(),
+        _ => false,
+    }
+}
+
+/// The algorithm a /CHECKSUM value names
+fn checksum_algorithm(value: &str) -> Option<ChecksumAlgorithm> {
+    match value.to_ascii_uppercase().as_str() {
+        "XXH3" => Some(ChecksumAlgorithm::Xxh3),
+        "SHA256" | "SHA-256" => Some(ChecksumAlgorithm::Sha256),
+        _ => None,
+    }
+}
+
+/// Parse the numeric value of `option`
+fn number<T: FromStr>(option: &str, value: &str) -> Result<T, UsageError> {
+    value
+        .parse()
+        .or_else(|_| error(format!("Invalid value for {}: '{}' is not a number", option, value)))
+}
+
+/// Set the option `spec` stands for; `display` is how the option was written
+fn apply(options: &mut CopyOptionsBuilder, spec: &OptionSpec, display: &str, value: Option<&str>) -> Result<(), UsageError> {
+    match spec.name {
+        "/S" => {
+            options.recursive(true);
+        },
+        "/E" => {
+            options.recursive(true).include_empty(true);
+        },
+        "/Z" => {
+            options.restartable(true);
+        },
+        "/B" => {
+            options.backup_mode(true);
+        },
+        "/PURGE" => {
+            options.purge(true);
+        },
+        "/PURGEALL" => {
+            options.purge(true).purge_all(true);
+        },
+        "/MAXDEL" => {
+            let value = value.unwrap_or_default();
+            let limit = match value.strip_suffix('%') {
+                Some(percent) => {
+                    let percent = number::<u8>(display, percent)?;
+                    if percent > 100 {
+                        return error(format!("Invalid value for {}: {}% is more than 100 percent", display, percent));
+                    }
+                    DeleteLimit::Percent(percent)
+                }
+                None => DeleteLimit::Count(number(display, value)?),
+            };
+            options.max_delete(limit);
+        },
+        "/FORCE" => {
+            options.force(true);
+        },
+        "/BACKUPDIR" => {
+            match value {
+                Some(backup_dir) if !backup_dir.is_empty() => options.backup_dir(backup_dir),
+                _ => return error(format!("Option {} requires a directory", display)),
+            };
+        },
+        "/BACKUPKEEP" => {
+            let versions = number::<usize>(display, value.unwrap_or_default())?;
+            if versions == 0 {
+                return error(format!("Invalid value for {}: at least one backup must be kept", display));
+            }
+            options.backup_versions(versions);
+        },
+        "/BACKUPDAYS" => {
+            let days = number::<u64>(display, value.unwrap_or_default())?;
+            if days == 0 {
+                return error(format!("Invalid value for {}: backups must be kept at least one day", display));
+            }
+            options.backup_days(days);
+        },
+        "/MIR" => {
+            options.purge(true).recursive(true).include_empty(true);
+        },
+        "/MOV" => {
+            options.move_files(true);
+        },
+        "/MOVE" => {
+            options.move_files(true).move_dirs(true);
+        },
+        "/VERIFYMOVE" => {
+            options.verify_moves(true);
+        },
+        "/A+" => {
+            options.attributes_add(value.unwrap_or_default().to_ascii_uppercase());
+        },
+        "/A-" => {
+            options.attributes_remove(value.unwrap_or_default().to_ascii_uppercase());
+        },
+        "/MT" => {
+            let threads = match value {
+                Some(value) => number::<usize>(display, value)?,
+                None => 8,
+            };
+            if threads == 0 {
+                return error(format!("Invalid value for {}: at least one thread is needed", display));
+            }
+            options.threads(threads);
+        },
+        "/R" => {
+            options.retries(number(display, value.unwrap_or_default())?);
+        },
+        "/W" => {
+            options.wait_time(number(display, value.unwrap_or_default())?);
+        },
+        "/BACKOFF" => {
+            let backoff = number::<u32>(display, value.unwrap_or_default())?;
+            if backoff == 0 {
+                return error(format!("Invalid value for {}: the multiplier must be at least 1", display));
+            }
+            options.retry_backoff(backoff);
+        },
+        "/WMAX" => {
+            options.max_wait_time(number(display, value.unwrap_or_default())?);
+        },
+        "/WTOTAL" => {
+            options.max_total_wait(number(display, value.unwrap_or_default())?);
+        },
+        "/JITTER" => {
+            let jitter = number::<u64>(display, value.unwrap_or_default())?;
+            if jitter > 100 {
+                return error(format!("Invalid value for {}: {} is more than 100 percent", display, jitter));
+            }
+            options.retry_jitter(jitter);
+        },
+        "/XF" => {
+            options.exclude_file(value.unwrap_or_default());
+        },
+        "/XD" => {
+            options.exclude_dir(value.unwrap_or_default());
+        },
+        "/XO" => {
+            options.exclude_older(true);
+        },
+        "/IO" => {
+            options.include_older(true);
+        },
+        "/XN" => {
+            options.exclude_newer(true);
+        },
+        "/XC" => {
+            options.exclude_changed(true);
+        },
+        "/XX" => {
+            options.exclude_extra(true);
+        },
+        "/XL" => {
+            options.exclude_lonely(true);
+        },
+        "/IS" => {
+            options.include_same(true);
+        },
+        "/IT" => {
+            options.include_tweaked(true);
+        },
+        "/IGNOREFILES" => {
+            options.use_ignore_files(true);
+        },
+        "/LOG" => {
+            match value {
+                Some(log_path) if !log_path.is_empty() => options.log_file(log_path),
+                _ => return error(format!("Option {} requires a file name", display)),
+            };
+        },
+        "/L" => {
+            options.list_only(true);
+        },
+        "/NP" => {
+            options.show_progress(false);
+        },
+        "/NFL" => {
+            options.log_file_names(false);
+        },
+        "/EMPTY" => {
+            options.empty_files(true);
+        },
+        "/CHILDONLY" => {
+            options.child_only(true);
+        },
+        "/SHRED" => {
+            options.shred_files(true);
+        },
+        "/ATOMIC" => {
+            options.atomic_writes(true);
+        },
+        "/REFLINK" => {
+            options.reflink(true);
+        },
+        "/SPARSE" => {
+            options.sparsify(true);
+        },
+        "/V" => {
+            options.verify(true);
+        },
+        "/CHECKSUM" => {
+            let algorithm = match value {
+                Some(value) => match checksum_algorithm(value) {
+                    Some(algorithm) => algorithm,
+                    None => return error(format!("Invalid value for {}: use XXH3 or SHA256", display)),
+                },
+                None => ChecksumAlgorithm::Xxh3,
+            };
+            options.checksum(algorithm);
+        },
+        _ => return error(format!("Unsupported option: {}", spec.name)),
+    }
+    Ok(())
+}
+
+pub fn print_usage(program_name: &str) {
+    println!("Usage: {} <source> <destination> [<file_pattern>...] [options]", program_name);
+    println!("Options can be given anywhere, in Robocopy (/MIR) or GNU (--mirror, -m) style; -- ends the options.");
+    println!("Options:");
+    for spec in OPTIONS {
+        let robocopy = match spec.value {
+            Value::None => spec.name.to_string(),
+            Value::Required(name) => format!("{}:{}", spec.name, name),
+            Value::Optional(name) => format!("{}[:{}]", spec.name, name),
+            Value::List(name) => format!("{} {}...", spec.name, name),
+        };
+        let long = match spec.value {
+            Value::None => format!("--{}", spec.long),
+            Value::Required(name) | Value::List(name) => format!("--{}={}", spec.long, name),
+            Value::Optional(name) => format!("--{}[={}]", spec.long, name),
+        };
+        let gnu = match spec.short {
+            Some(short) => format!("-{}, {}", short, long),
+            None => format!("    {}", long),
+        };
+        println!("  {:<15} {:<30} - {}", robocopy, gnu, spec.help);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Arguments>, UsageError> {
+        parse_args(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    /// The options a command line sets
+    fn options(args: &[&str]) -> CopyOptions {
+        parse(args).unwrap().unwrap().options
+    }
+
+    #[test]
+    fn gnu_and_robocopy_options_are_equivalent() {
+        let robocopy = options(&["src", "dst", "/MIR", "/MT:16", "/R:5", "/L"]);
+        let expected = CopyOptions::builder()
+            .purge(true)
+            .recursive(true)
+            .include_empty(true)
+            .threads(16)
+            .retries(5)
+            .list_only(true)
+            .build();
+        assert_eq!(robocopy, expected);
+        assert_eq!(options(&["src", "dst", "--mirror", "--threads=16", "--retries", "5", "--list"]), robocopy);
+        assert_eq!(options(&["src", "dst", "-m", "--threads", "16", "-r5", "-l"]), robocopy);
+        assert_eq!(options(&["src", "dst", "/mir", "/mt:16", "/r:5", "/l"]), robocopy);
+    }
+
+    #[test]
+    fn short_options_can_be_grouped() {
+        let expected = CopyOptions::builder().recursive(true).include_empty(true).retries(3).build();
+        assert_eq!(options(&["src", "dst", "-se", "-r", "3"]), expected);
+    }
+
+    #[test]
+    fn options_can_appear_anywhere() {
+        let arguments = parse(&["--mirror", "src", "-l", "dst", "/data/*.log", "--retries=2"]).unwrap().unwrap();
+        assert_eq!(arguments.source, "src");
+        assert_eq!(arguments.destination, "dst");
+        assert_eq!(arguments.file_patterns, ["/data/*.log"]);
+        let expected =
+            CopyOptions::builder().purge(true).recursive(true).include_empty(true).list_only(true).retries(2).build();
+        assert_eq!(arguments.options, expected);
+    }
+
+    #[test]
+    fn double_dash_ends_the_options() {
+        let arguments = parse(&["-l", "--", "-src", "/MIR", "--list"]).unwrap().unwrap();
+        assert_eq!(arguments.source, "-src");
+        assert_eq!(arguments.destination, "/MIR");
+        assert_eq!(arguments.file_patterns, ["--list"]);
+    }
+
+    #[test]
+    fn help_is_not_a_job() {
+        assert!(parse(&["--help"]).unwrap().is_none());
+        assert!(parse(&["src", "dst", "-h"]).unwrap().is_none());
+        assert!(parse(&["/?"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn malformed_gnu_options_are_refused() {
+        let message = |args: &[&str]| parse(args).err().unwrap().to_string();
+        assert_eq!(message(&["src", "dst", "--mirorr"]), "Unknown option: --mirorr");
+        assert_eq!(message(&["src", "dst", "-q"]), "Unknown option: -q");
+        assert_eq!(message(&["src", "dst", "--list=yes"]), "Option --list does not take a value");
+        assert_eq!(message(&["src", "dst", "--retries"]), "Option --retries requires a value (n)");
+        assert_eq!(message(&["src"]), "Missing source or destination directory");
+    }
+
+    #[test]
+    fn exclusion_list_stops_at_a_mistyped_option() {
+        let error = parse(&["src", "dst", "/XF", "*.tmp", "/PURGUE"]).err().unwrap();
+        assert_eq!(error.to_string(), "Unknown option: /PURGUE (did you mean /PURGE?)");
+    }
+
+    #[test]
+    fn exclusion_list_stops_at_the_next_option() {
+        let arguments = parse(&["src", "dst", "/XD", "node_modules", ".git", "/MIR", "*.jpg"]).unwrap().unwrap();
+        assert_eq!(arguments.file_patterns, ["*.jpg"]);
+        let expected = CopyOptions::builder()
+            .exclude_dir("node_modules")
+            .exclude_dir(".git")
+            .purge(true)
+            .recursive(true)
+            .include_empty(true)
+            .build();
+        assert_eq!(arguments.options, expected);
+    }
+
+    #[test]
+    fn optional_value_may_be_the_next_argument() {
+        let arguments = parse(&["src", "dst", "--threads", "4", "--checksum", "sha256"]).unwrap().unwrap();
+        assert!(arguments.file_patterns.is_empty());
+        assert_eq!(arguments.options, CopyOptions::builder().threads(4).checksum(ChecksumAlgorithm::Sha256).build());
+    }
+
+    #[test]
+    fn optional_value_leaves_other_arguments_alone() {
+        let arguments = parse(&["src", "dst", "--checksum", "*.jpg"]).unwrap().unwrap();
+        assert_eq!(arguments.file_patterns, ["*.jpg"]);
+        assert_eq!(arguments.options, CopyOptions::builder().checksum(ChecksumAlgorithm::Xxh3).build());
+    }
+
+    #[test]
+    fn suggest_finds_the_option_a_typo_meant() {
+        let suggested = |name: &str| suggest(name).map(|spec| spec.name);
+        assert_eq!(suggested("/PURGUE"), Some("/PURGE"));
+        assert_eq!(suggested("/purgue"), Some("/PURGE"));
+        assert_eq!(suggested("/MT16"), Some("/MT"));
+        assert_eq!(suggested("/MIRR"), Some("/MIR"));
+        assert_eq!(suggested("/CHEKSUM"), Some("/CHECKSUM"));
+        assert_eq!(suggested("/FROBNICATE"), None);
+        assert_eq!(suggested("/mnt"), None);
+        assert_eq!(suggested("/srv"), None);
+        assert_eq!(suggested("/share"), None);
+    }
+
+    #[test]
+    fn unknown_options_are_refused() {
+        let message = |args: &[&str]| parse(args).err().unwrap().to_string();
+        assert_eq!(message(&["src", "dst", "/PURGUE"]), "Unknown option: /PURGUE (did you mean /PURGE?)");
+        assert_eq!(message(&["src", "dst", "/MT16"]), "Unknown option: /MT16 (did you mean /MT?)");
+        assert_eq!(
+            message(&["src", "dst", "/FROB:1"]),
+            "Unknown option: /FROB (put -- before paths that look like options)"
+        );
+    }
+
+    #[test]
+    fn single_component_paths_are_not_options() {
+        let arguments = parse(&["/data/src", "/newdest", "/photos", "/logs"]).unwrap().unwrap();
+        assert_eq!(arguments.source, "/data/src");
+        assert_eq!(arguments.destination, "/newdest");
+        assert_eq!(arguments.file_patterns, ["/photos", "/logs"]);
+    }
+
+    #[test]
+    fn invalid_values_are_refused() {
+        let message = |args: &[&str]| parse(args).err().unwrap().to_string();
+        assert_eq!(message(&["src", "dst", "/R:abc"]), "Invalid value for /R: 'abc' is not a number");
+        assert_eq!(message(&["src", "dst", "--wait=-1"]), "Invalid value for --wait: '-1' is not a number");
+        assert_eq!(message(&["src", "dst", "/MT:0"]), "Invalid value for /MT: at least one thread is needed");
+    }
+
+    #[test]
+    fn contradictory_options_are_refused() {
+        let message = |args: &[&str]| parse(args).err().unwrap().to_string();
+        assert_eq!(
+            message(&["src", "dst", "/L", "/MOVE"]),
+            "/L cannot be combined with /MOVE, a listing never changes the source"
+        );
+        assert_eq!(
+            message(&["src", "dst", "/EMPTY", "/MOV"]),
+            "/EMPTY cannot be combined with /MOV, the source files would be replaced by empty copies"
+        );
+        assert_eq!(message(&["src", "dst", "/IO", "/XO"]), "/IO cannot be combined with /XO");
+    }
+}
@@ -0,0 +1,602 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rand::{thread_rng, Rng};
+
+use crate::backup::Backup;
+use crate::cancel::{cancelled_error, is_cancelled_error, CancellationToken};
+use crate::checkpoint::{checkpoint_path, checkpoint_target, Checkpoint, CHECKPOINT_INTERVAL};
+use crate::class::{classify, same_or_tweaked, FileClass};
+use crate::engine::report_file_failure;
+use crate::fast_copy::{drop_cache, extend_to, is_sparse, next_data, reflink, ChunkCopier};
+use crate::hash::{hash_reader, same_checksum, same_contents};
+use crate::observer::{CopyEvent, CopyObserver, SkipReason};
+use crate::options::CopyOptions;
+use crate::shred::securely_delete_file;
+use crate::stats::Statistics;
+
+pub(crate) fn copy_file(
+    src_path: &Path,
+    dst_path: &Path,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+    cancel: &CancellationToken,
+    backup: Option<&Backup>,
+) -> io::Result<()> {
+    let src_meta = fs::metadata(src_path)?;
+    let dst_meta = fs::metadata(dst_path).ok();
+
+    // Atomic writes go to a hidden temporary file that only replaces the destination once complete
+    let write_path = if options.atomic_writes {
+        temp_path(dst_path)
+    } else {
+        dst_path.to_path_buf()
+    };
+
+    // A leftover checkpoint means the destination is only partially written, whatever its timestamp says
+    let resuming = options.restartable && !options.empty_files && checkpoint_path(&write_path).exists();
+
+    let mut class = classify(&src_meta, dst_meta.as_ref());
+
+    // With /CHECKSUM the contents decide whether files of the same size differ; different files
+    // keep the Newer or Older class of their timestamps, so that /XO and /XN still protect them
+    if let Some(algorithm) = options.checksum
+        && let Some(dst_meta) = &dst_meta
+        && !resuming
+        && src_meta.len() == dst_meta.len()
+    {
+        let same = match same_checksum(src_path, dst_path, algorithm) {
+            Ok(same) => same,
+            Err(e) => {
+                report_file_failure("compare checksums", src_path, &e, observer, stats);
+                return Ok(());
+            }
+        };
+        let mut stats = stats.lock().unwrap();
+        stats.files_checksummed += 1;
+        stats.bytes_checksummed += src_meta.len() * 2;
+        class = match class {
+            _ if same => same_or_tweaked(&src_meta, dst_meta),
+            FileClass::Same | FileClass::Tweaked => FileClass::Changed,
+            class => class,
+        };
+    }
+
+    // A partial file from an interrupted copy is finished whatever its class, its timestamp is not the real one
+    if !resuming {
+        stats.lock().unwrap().count_class(class);
+    }
+    if !resuming && !class.is_copied(options) {
+        observer.event(&CopyEvent::FileSkipped { src: src_path, dst: dst_path, reason: SkipReason::Class(class) });
+        stats.lock().unwrap().files_skipped += 1;
+        return Ok(());
+    }
+
+    // The file about to be overwritten goes to the backup first; an atomic write only replaces
+    // it once the new copy is complete, so that is when it gets backed up
+    let mut needs_backup = backup.is_some() && dst_meta.is_some() && !resuming;
+    let mut save_backup = || match backup {
+        Some(backup) if needs_backup => {
+            needs_backup = false;
+            backup.save(dst_path, observer, stats)
+        }
+        _ => Ok(()),
+    };
+
+    // On one file system a move is a rename, no data has to be copied
+    if options.move_files
+        && !options.list_only
+        && options.attributes_add.is_empty()
+        && options.attributes_remove.is_empty()
+        && same_device(&src_meta, dst_path)
+    {
+        save_backup()?;
+        match fs::rename(src_path, dst_path) {
+            Ok(_) => {
+                // Whatever an earlier, interrupted copy left behind is obsolete now
+                if resuming {
+                    if options.atomic_writes {
+                        let _ = fs::remove_file(&write_path);
+                    }
+                    let _ = fs::remove_file(checkpoint_path(&write_path));
+                }
+
+                observer.event(&CopyEvent::FileMoved { src: src_path, dst: dst_path });
+                stats.lock().unwrap().files_moved += 1;
+                return Ok(());
+            }
+            // Across file systems the file is copied and the source deleted afterwards
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    observer.event(&CopyEvent::FileStarted {
+        src: src_path,
+        dst: dst_path,
+        size: src_meta.len(),
+        class,
+        list_only: options.list_only,
+    });
+
+    if options.list_only {
+        let mut stats = stats.lock().unwrap();
+        stats.files_copied += 1;
+        stats.bytes_copied += src_meta.len();
+        return Ok(());
+    }
+
+    if !options.atomic_writes {
+        save_backup()?;
+    }
+
+    let mut retry_count = 0;
+    let mut total_wait = Duration::from_secs(0);
+    loop {
+        let result = copy_file_with_progress(src_path, &write_path, &src_meta, options, observer, cancel)
+            .and_then(|bytes_written| {
+                if options.atomic_writes {
+                    save_backup()?;
+                }
+                commit_file(&write_path, dst_path, &src_meta, class, options)?;
+                Ok(bytes_written)
+            });
+
+        match result {
+            Ok(bytes_written) => {
+                // Set/unset attributes if specified
+                #[cfg(windows)]
+                {
+                    use std::os::windows::fs::MetadataExt;
+
+                    if !options.attributes_add.is_empty() || !options.attributes_remove.is_empty() {
+                        if let Ok(metadata) = fs::metadata(dst_path) {
+                            let mut attributes = metadata.file_attributes();
+
+                            // Add attributes
+                            for c in options.attributes_add.chars() {
+                                match c {
+                                    'R' => attributes |= 0x00000001, // FILE_ATTRIBUTE_READONLY
+                                    'A' => attributes |= 0x00000020, // FILE_ATTRIBUTE_ARCHIVE
+                                    'S' => attributes |= 0x00000004, // FILE_ATTRIBUTE_SYSTEM
+                                    'H' => attributes |= 0x00000002, // FILE_ATTRIBUTE_HIDDEN
+                                    'C' => attributes |= 0x00000800, // FILE_ATTRIBUTE_COMPRESSED
+                                    'N' => attributes |= 0x00000080, // FILE_ATTRIBUTE_NORMAL
+                                    _ => {}
+                                }
+                            }
+
+                            // Remove attributes
+                            for c in options.attributes_remove.chars() {
+                                match c {
+                                    'R' => attributes &= !0x00000001,
+                                    'A' => attributes &= !0x00000020,
+                                    'S' => attributes &= !0x00000004,
+                                    'H' => attributes &= !0x00000002,
+                                    'C' => attributes &= !0x00000800,
+                                    'N' => attributes &= !0x00000080,
+                                    _ => {}
+                                }
+                            }
+
+                            // Apply attributes
+                            let _ = std::process::Command::new("attrib")
+                                .arg(format!("+{}", attributes))
+                                .arg(dst_path.to_string_lossy().to_string())
+                                .output();
+                        }
+                    }
+                }
+
+                // Move (delete source) if requested
+                if options.move_files {
+                    remove_moved_source(src_path, dst_path, options, observer, stats);
+                }
+
+                observer.event(&CopyEvent::FileCopied { src: src_path, dst: dst_path, bytes: src_meta.len() });
+
+                let mut stats = stats.lock().unwrap();
+                stats.files_copied += 1;
+                stats.bytes_copied += src_meta.len();
+                stats.bytes_written += bytes_written;
+                break;
+            }
+            // The partial output was already dealt with, there is nothing to retry
+            Err(e) if is_cancelled_error(&e) => return Err(e),
+            Err(e) => {
+                if is_verification_error(&e) {
+                    stats.lock().unwrap().files_verify_failed += 1;
+                }
+
+                retry_count += 1;
+                let delay = retry_delay(options, retry_count);
+                let out_of_time = options.max_total_wait > 0
+                    && total_wait.saturating_add(delay) > Duration::from_secs(options.max_total_wait);
+
+                if !is_transient_error(&e) || retry_count >= options.retries || out_of_time || cancel.is_cancelled() {
+                    if !is_transient_error(&e) {
+                        observer.event(&CopyEvent::Notice {
+                            message: &format!("Not retrying, the error is permanent: {}", src_path.display()),
+                        });
+                    } else if out_of_time {
+                        observer.event(&CopyEvent::Notice {
+                            message: &format!("Giving up after waiting {} seconds: {}", total_wait.as_secs(), src_path.display()),
+                        });
+                    }
+
+                    // Without a checkpoint the temporary file can never be resumed
+                    if options.atomic_writes && !options.restartable {
+                        let _ = fs::remove_file(&write_path);
+                    }
+                    return Err(e);
+                }
+
+                observer.event(&CopyEvent::FileRetried {
+                    src: src_path,
+                    dst: dst_path,
+                    attempt: retry_count,
+                    retries: options.retries,
+                    delay,
+                    error: &e,
+                });
+
+                cancel.sleep(delay);
+                total_wait = total_wait.saturating_add(delay);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete the source of a file that was moved by copying it
+///
+/// With /VERIFYMOVE the source is only deleted once the copy read back from the destination has
+/// the same SHA-256, otherwise it is kept and the file counts as failed.
+fn remove_moved_source(
+    src_path: &Path,
+    dst_path: &Path,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+) {
+    if options.verify_moves {
+        let verified = same_contents(src_path, dst_path).and_then(|same| {
+            if same {
+                Ok(())
+            } else {
+                Err(io::Error::new(io::ErrorKind::InvalidData, "the copy differs from the source, the source was kept"))
+            }
+        });
+        if let Err(e) = verified {
+            report_file_failure("verify moved file", dst_path, &e, observer, stats);
+            return;
+        }
+    }
+
+    let result = if options.shred_files {
+        securely_delete_file(src_path, observer)
+    } else {
+        fs::remove_file(src_path)
+    };
+    if let Err(e) = result {
+        report_file_failure("remove source file", src_path, &e, observer, stats);
+    }
+}
+
+/// Whether `dst_path` is on the file system of the source, so that moving it there can be a rename
+#[cfg(unix)]
+pub(crate) fn same_device(src_meta: &Metadata, dst_path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    // The destination itself may not exist yet, its directory does
+    let dst_dir = dst_path.parent().unwrap_or(dst_path);
+    fs::metadata(dst_dir).is_ok_and(|dst_meta| dst_meta.dev() == src_meta.dev())
+}
+
+/// Without device ids the rename is just tried, it fails with `CrossesDevices` between volumes
+#[cfg(not(unix))]
+pub(crate) fn same_device(_src_meta: &Metadata, _dst_path: &Path) -> bool {
+    true
+}
+
+/// Whether an error is worth retrying, or would just fail again the same way
+fn is_transient_error(e: &io::Error) -> bool {
+    // Everything else, like timeouts, EIO on network mounts or EAGAIN, may go away on its own
+    !matches!(
+        e.kind(),
+        io::ErrorKind::PermissionDenied
+            | io::ErrorKind::NotFound
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::InvalidFilename
+            | io::ErrorKind::ReadOnlyFilesystem
+            | io::ErrorKind::IsADirectory
+            | io::ErrorKind::NotADirectory
+            | io::ErrorKind::FileTooLarge
+            | io::ErrorKind::StorageFull
+            | io::ErrorKind::QuotaExceeded
+            | io::ErrorKind::Unsupported
+    )
+}
+
+/// Wait before the given retry: exponential backoff, capped and randomly varied by the jitter
+fn retry_delay(options: &CopyOptions, retry_count: usize) -> Duration {
+    let exponent = i32::try_from(retry_count.saturating_sub(1)).unwrap_or(i32::MAX);
+    let backoff = options.wait_time as f64 * f64::from(options.retry_backoff.max(1)).powi(exponent);
+    let capped = backoff.min(options.max_wait_time.max(options.wait_time) as f64);
+
+    let jitter = capped * options.retry_jitter as f64 / 100.0;
+    let delay = if jitter > 0.0 {
+        capped + thread_rng().gen_range(-jitter..=jitter)
+    } else {
+        capped
+    };
+
+    // Waits like /W:18446744073709551615 do not fit in a Duration, they are as good as forever
+    Duration::try_from_secs_f64(delay.max(0.0)).unwrap_or(Duration::MAX)
+}
+
+/// Suffix of the hidden temporary file an atomic write goes to before it is renamed into place
+const TEMP_SUFFIX: &str = ".colemen_tmp";
+
+/// Hidden temporary path an atomic write of `dst_path` goes to
+fn temp_path(dst_path: &Path) -> PathBuf {
+    let file_name = dst_path.file_name().unwrap_or_default().to_string_lossy();
+    dst_path.with_file_name(format!(".{}{}", file_name, TEMP_SUFFIX))
+}
+
+/// Returns the destination file name a temporary file belongs to, if `file_name` is one
+fn temp_target(file_name: &str) -> Option<&str> {
+    file_name.strip_prefix('.')?.strip_suffix(TEMP_SUFFIX)
+}
+
+/// Returns the destination file name a checkpoint or temporary file belongs to
+pub(crate) fn partial_target(file_name: &str) -> Option<&str> {
+    match checkpoint_target(file_name) {
+        Some(name) => Some(temp_target(name).unwrap_or(name)),
+        None => temp_target(file_name),
+    }
+}
+
+/// Apply the source timestamps to a freshly written file and move it into place
+fn commit_file(
+    write_path: &Path,
+    dst_path: &Path,
+    src_meta: &Metadata,
+    class: FileClass,
+    options: &CopyOptions,
+) -> io::Result<()> {
+    // Preserve file modification time
+    if let Ok(src_time) = src_meta.modified() {
+        let _ = filetime::set_file_mtime(write_path, filetime::FileTime::from_system_time(src_time));
+    }
+
+    // Permissions are what makes a tweaked file differ, copying it (/IT) brings them in line
+    if class == FileClass::Tweaked {
+        fs::set_permissions(write_path, src_meta.permissions())?;
+    }
+
+    if options.atomic_writes {
+        fs::rename(write_path, dst_path)?;
+    }
+
+    // The file is complete, so the restart checkpoint is no longer needed
+    if options.restartable {
+        let _ = fs::remove_file(checkpoint_path(write_path));
+    }
+
+    Ok(())
+}
+
+/// Remove temporary files left behind by atomic writes of an earlier, interrupted run
+pub(crate) fn remove_stale_temp_files(dst_dir: &Path, options: &CopyOptions, observer: &dyn CopyObserver) {
+    let Ok(entries) = fs::read_dir(dst_dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if temp_target(&file_name).is_none() || !path.is_file() {
+            continue;
+        }
+
+        // A checkpointed temporary file is picked up again by the restartable copy
+        if options.restartable && checkpoint_path(&path).exists() {
+            continue;
+        }
+
+        observer.event(&CopyEvent::Notice { message: &format!("Removing stale temporary file: {}", path.display()) });
+        let _ = fs::remove_file(&path);
+    }
+}
+
+fn copy_file_with_progress(
+    src_path: &Path,
+    dst_path: &Path,
+    src_meta: &Metadata,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    cancel: &CancellationToken,
+) -> io::Result<u64> {
+    // The destination is left untouched when the job was cancelled while waiting for a retry
+    if cancel.is_cancelled() {
+        return Err(cancelled_error());
+    }
+
+    // If empty_files option is enabled, just create an empty file
+    if options.empty_files {
+        let mut dst_file = File::create(dst_path)?;
+        dst_file.flush()?;
+        if options.atomic_writes {
+            dst_file.sync_all()?;
+        }
+        return Ok(0);
+    }
+
+    let total_size = src_meta.len();
+    let mut src_file = File::open(src_path)?;
+
+    // In restartable mode continue from the last committed offset of an earlier attempt
+    let checkpoint_file = checkpoint_path(dst_path);
+    let mut resume_from = 0;
+    if options.restartable
+        && let Some(checkpoint) = Checkpoint::load(&checkpoint_file)
+        && checkpoint.matches(src_meta)
+        && fs::metadata(dst_path).map(|meta| meta.len() >= checkpoint.bytes_committed).unwrap_or(false)
+    {
+        resume_from = checkpoint.bytes_committed;
+    }
+
+    let mut dst_file = if resume_from > 0 {
+        observer.event(&CopyEvent::FileResumed { dst: dst_path, offset: resume_from });
+
+        let mut dst_file = fs::OpenOptions::new().write(true).open(dst_path)?;
+        // Anything past the committed offset was never verified, drop it
+        dst_file.set_len(resume_from)?;
+        dst_file.seek(io::SeekFrom::Start(resume_from))?;
+        src_file.seek(io::SeekFrom::Start(resume_from))?;
+        dst_file
+    } else {
+        let dst_file = File::create(dst_path)?;
+        if options.restartable {
+            Checkpoint::new(src_meta, 0).save(&checkpoint_file)?;
+        }
+        dst_file
+    };
+
+    // A reflink shares the data instead of copying it, which is instant but all or nothing
+    if options.reflink && resume_from == 0 && total_size > 0 && reflink(&src_file, &dst_file)? {
+        observer.event(&CopyEvent::FileProgress { dst: dst_path, bytes_copied: total_size, total_bytes: total_size });
+        finish_file(dst_file, options)?;
+        return Ok(0);
+    }
+
+    // Holes of a sparse source are skipped, so that they stay holes in the destination
+    let sparse_source = is_sparse(src_meta);
+    // /V needs to see the data to hash it, so it cannot be copied inside the kernel
+    let mut copier = ChunkCopier::new(!options.verify, options.sparsify);
+    if options.verify {
+        copier.hash(options.checksum.unwrap_or_default());
+        // What an earlier attempt copied is hashed from the source, the verification reads all of the copy
+        if resume_from > 0 {
+            src_file.seek(io::SeekFrom::Start(0))?;
+            copier.hash_source(&mut src_file, resume_from)?;
+        }
+    }
+    let mut bytes_copied: u64 = resume_from;
+    let mut last_checkpoint = resume_from;
+
+    loop {
+        if cancel.is_cancelled() {
+            if options.restartable {
+                // Keep the partial file resumable from everything written so far
+                extend_to(&dst_file, bytes_copied)?;
+                dst_file.sync_data()?;
+                Checkpoint::new(src_meta, bytes_copied).save(&checkpoint_file)?;
+            } else {
+                drop(dst_file);
+                let _ = fs::remove_file(dst_path);
+            }
+            return Err(cancelled_error());
+        }
+
+        let mut limit = u64::MAX;
+        if sparse_source {
+            let Some((data_start, data_end)) = next_data(&mut src_file, bytes_copied)? else {
+                copier.hash_zeros(total_size.saturating_sub(bytes_copied));
+                bytes_copied = total_size;
+                break;
+            };
+            if data_start > bytes_copied {
+                copier.hash_zeros(data_start - bytes_copied);
+                dst_file.seek(io::SeekFrom::Start(data_start))?;
+                bytes_copied = data_start;
+            }
+            limit = data_end - data_start;
+        }
+
+        let bytes_read = copier.copy_chunk(&mut src_file, &mut dst_file, limit)?;
+        if bytes_read == 0 {
+            break;
+        }
+
+        // If restartable mode is enabled, flush after each write
+        if options.restartable {
+            dst_file.flush()?;
+        }
+
+        bytes_copied += bytes_read as u64;
+
+        // Commit the data to disk before recording it, so a resume never trusts unwritten bytes
+        if options.restartable && bytes_copied - last_checkpoint >= CHECKPOINT_INTERVAL {
+            extend_to(&dst_file, bytes_copied)?;
+            dst_file.sync_data()?;
+            Checkpoint::new(src_meta, bytes_copied).save(&checkpoint_file)?;
+            last_checkpoint = bytes_copied;
+        }
+
+        observer.event(&CopyEvent::FileProgress { dst: dst_path, bytes_copied, total_bytes: total_size });
+    }
+
+    // Holes at the end were never written, they only exist once the file has its full size
+    extend_to(&dst_file, bytes_copied)?;
+    finish_file(dst_file, options)?;
+
+    if let Some(expected) = copier.finish_hash() {
+        verify_copy(dst_path, &expected, options)?;
+    }
+    Ok(copier.bytes_written())
+}
+
+fn finish_file(mut dst_file: File, options: &CopyOptions) -> io::Result<()> {
+    dst_file.flush()?;
+
+    // The data must be on disk before the rename makes it visible under the final name, and
+    // before /V reads it back
+    if options.atomic_writes || options.verify {
+        dst_file.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Read a copy back and compare it with the hash of the data that was written to it (/V)
+fn verify_copy(dst_path: &Path, expected: &[u8], options: &CopyOptions) -> io::Result<()> {
+    let dst_file = File::open(dst_path)?;
+    // Cached pages would only show what was written, not what reached the disk
+    drop_cache(&dst_file);
+    if hash_reader(dst_file, options.checksum.unwrap_or_default())? == expected {
+        return Ok(());
+    }
+
+    // The checkpoint vouches for data that turned out to be wrong, the retry has to start over
+    let _ = fs::remove_file(checkpoint_path(dst_path));
+    Err(verification_error())
+}
+
+/// Error payload of a copy that differs from the source when read back (/V)
+#[derive(Debug)]
+struct VerificationFailed;
+
+impl fmt::Display for VerificationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The copy read back from the destination differs from the source")
+    }
+}
+
+impl Error for VerificationFailed {}
+
+fn verification_error() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, VerificationFailed)
+}
+
+fn is_verification_error(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<VerificationFailed>())
+}
@@ -0,0 +1,745 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::backup::Backup;
+use crate::cancel::{is_cancelled_error, CancellationToken};
+use crate::class::FileClass;
+use crate::copy::{copy_file, partial_target, remove_stale_temp_files, same_device};
+use crate::observer::{CopyEvent, CopyObserver, EntryKind, SkipReason};
+use crate::options::CopyOptions;
+use crate::pattern::Filter;
+use crate::safety::DESTINATION_MARKER;
+use crate::shred::{secure_remove_dir_all, securely_delete_file};
+use crate::stats::Statistics;
+
+/// Number of queued file jobs allowed per worker before the directory walk blocks
+const QUEUE_DEPTH_PER_THREAD: usize = 64;
+
+/// Tracks the file jobs of one directory that are still queued or being copied
+#[derive(Default)]
+struct PendingJobs {
+    count: Mutex<usize>,
+    finished: Condvar,
+}
+
+impl PendingJobs {
+    fn add(&self) {
+        *self.count.lock().unwrap() += 1;
+    }
+
+    fn done(&self) {
+        let mut count = self.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.finished.notify_all();
+        }
+    }
+
+    fn wait(&self) {
+        let mut count = self.count.lock().unwrap();
+        while *count > 0 {
+            count = self.finished.wait(count).unwrap();
+        }
+    }
+}
+
+/// A single file copy handed from the directory walk to a worker
+struct FileJob {
+    src_path: PathBuf,
+    dst_path: PathBuf,
+    pending: Arc<PendingJobs>,
+}
+
+impl Drop for FileJob {
+    fn drop(&mut self) {
+        // Release the directory even if the job was skipped or the worker panicked
+        self.pending.done();
+    }
+}
+
+/// State of a running job that the walk and every worker share, besides its options
+pub(crate) struct JobContext<'a> {
+    pub(crate) cancel: &'a CancellationToken,
+    pub(crate) backup: Option<&'a Backup>,
+}
+
+/// Producer side of the copy engine, used by the directory walk
+struct CopyQueue<'a> {
+    sender: Option<SyncSender<FileJob>>,
+    context: &'a JobContext<'a>,
+}
+
+impl CopyQueue<'_> {
+    fn is_cancelled(&self) -> bool {
+        self.context.cancel.is_cancelled()
+    }
+
+    fn backup(&self) -> Option<&Backup> {
+        self.context.backup
+    }
+
+    fn submit(
+        &self,
+        src_path: PathBuf,
+        dst_path: PathBuf,
+        pending: &Arc<PendingJobs>,
+        options: &CopyOptions,
+        observer: &dyn CopyObserver,
+        stats: &Mutex<Statistics>,
+    ) {
+        // Nothing new is started once the job was cancelled
+        if self.is_cancelled() {
+            return;
+        }
+
+        pending.add();
+        let job = FileJob { src_path, dst_path, pending: Arc::clone(pending) };
+
+        match &self.sender {
+            Some(sender) => {
+                // Workers only hang up once the walk is over, so a failed send cannot happen here
+                let _ = sender.send(job);
+            }
+            None => run_job(&job, options, observer, stats, self.context),
+        }
+    }
+}
+
+fn run_job(
+    job: &FileJob,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+    context: &JobContext,
+) {
+    // Jobs still queued when the job is cancelled are dropped
+    if context.cancel.is_cancelled() {
+        return;
+    }
+
+    match copy_file(&job.src_path, &job.dst_path, options, observer, stats, context.cancel, context.backup) {
+        Ok(_) => {}
+        Err(e) if is_cancelled_error(&e) => {
+            observer.event(&CopyEvent::FileCancelled {
+                src: &job.src_path,
+                dst: &job.dst_path,
+                resumable: options.restartable,
+            });
+        }
+        Err(e) => report_file_failure("copy file", &job.src_path, &e, observer, stats),
+    }
+}
+
+/// Report a file that could not be processed and remember it for the final report
+pub(crate) fn report_file_failure(
+    action: &str,
+    path: &Path,
+    e: &io::Error,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+) {
+    observer.event(&CopyEvent::FileFailed { path, action, error: e });
+    let mut stats = stats.lock().unwrap();
+    stats.files_failed += 1;
+    stats.failures.push(format!("Failed to {} {}: {}", action, path.display(), e));
+}
+
+/// Report a directory that could not be processed and remember it for the final report
+fn report_dir_failure(
+    action: &str,
+    path: &Path,
+    e: &io::Error,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+) {
+    observer.event(&CopyEvent::DirectoryFailed { path, action, error: e });
+    let mut stats = stats.lock().unwrap();
+    stats.dirs_failed += 1;
+    stats.failures.push(format!("Failed to {} {}: {}", action, path.display(), e));
+}
+
+fn copy_worker(
+    receiver: &Mutex<Receiver<FileJob>>,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+    context: &JobContext,
+) {
+    loop {
+        // Only hold the receiver lock while waiting for the next job, not while copying
+        let job = match receiver.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => break,
+        };
+        run_job(&job, options, observer, stats, context);
+    }
+}
+
+/// Walk the source tree and copy its files, using a pool of `options.threads` workers
+pub(crate) fn run_copy(
+    source_path: &Path,
+    dest_path: &Path,
+    filter: &Filter,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+    context: &JobContext,
+) {
+    thread::scope(|scope| {
+        // A single thread copies inline so progress output stays on one line
+        let sender = if options.threads > 1 {
+            let (sender, receiver) = mpsc::sync_channel(options.threads * QUEUE_DEPTH_PER_THREAD);
+            let receiver = Arc::new(Mutex::new(receiver));
+            for _ in 0..options.threads {
+                let receiver = Arc::clone(&receiver);
+                scope.spawn(move || copy_worker(&receiver, options, observer, stats, context));
+            }
+            Some(sender)
+        } else {
+            None
+        };
+
+        let queue = CopyQueue { sender, context };
+
+        // Dropping the queue at the end of the walk closes the channel and lets the workers finish
+        walk_source(source_path, dest_path, filter, options, observer, stats, &queue);
+    });
+}
+
+fn walk_source(
+    source_path: &Path,
+    dest_path: &Path,
+    filter: &Filter,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+    queue: &CopyQueue,
+) {
+    // Handle child-only mode
+    if options.child_only && source_path.is_dir() {
+        let filter = match filter.enter(source_path) {
+            Ok(filter) => filter,
+            Err(e) => {
+                report_dir_failure("read ignore files in", source_path, &e, observer, stats);
+                return;
+            }
+        };
+
+        // Process each child directory individually
+        match fs::read_dir(source_path) {
+            Ok(entries) => for entry in entries.flatten() {
+                if queue.is_cancelled() {
+                    break;
+                }
+
+                let child_path = entry.path();
+                if child_path.is_dir() && filter.excludes_dir(&child_path) {
+                    observer.event(&CopyEvent::DirectorySkipped { path: &child_path, reason: SkipReason::Excluded });
+                    stats.lock().unwrap().dirs_skipped += 1;
+                } else if child_path.is_dir() && filter.ignores(&child_path, true) {
+                    observer.event(&CopyEvent::DirectorySkipped { path: &child_path, reason: SkipReason::Ignored });
+                    stats.lock().unwrap().dirs_skipped += 1;
+                } else if child_path.is_dir() {
+                    let child_name = child_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                    let child_dest = dest_path.join(&child_name);
+
+                    // Log the child directory processing
+                    observer.event(&CopyEvent::Notice {
+                        message: &format!("\nProcessing child directory: {}", child_name),
+                    });
+
+                    // Process this child directory
+                    copy_directory(
+                        &child_path,
+                        &child_dest,
+                        &filter,
+                        options,
+                        observer,
+                        stats,
+                        queue,
+                    );
+                }
+            },
+            Err(e) => report_dir_failure("read directory", source_path, &e, observer, stats),
+        }
+    } else {
+        // Regular mode - process the entire source directory
+        copy_directory(
+            source_path,
+            dest_path,
+            filter,
+            options,
+            observer,
+            stats,
+            queue,
+        );
+    }
+}
+
+fn copy_directory(
+    src_dir: &Path,
+    dst_dir: &Path,
+    filter: &Filter,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+    queue: &CopyQueue,
+) {
+    // Rules of ignore files in this directory apply to everything below it
+    let filter = match filter.enter(src_dir) {
+        Ok(filter) => filter,
+        Err(e) => {
+            report_dir_failure("read ignore files in", src_dir, &e, observer, stats);
+            return;
+        }
+    };
+
+    // Ensure the destination directory exists
+    if !dst_dir.exists() {
+        observer.event(&CopyEvent::DirectoryCreated { path: dst_dir, list_only: options.list_only });
+        if !options.list_only
+            && let Err(e) = fs::create_dir_all(dst_dir)
+        {
+            report_dir_failure("create directory", dst_dir, &e, observer, stats);
+            return;
+        }
+        stats.lock().unwrap().dirs_created += 1;
+    } else if options.atomic_writes && !options.list_only {
+        remove_stale_temp_files(dst_dir, options, observer);
+    }
+
+    // Collect the source files and directories
+    let mut src_entries = HashSet::new();
+    let pending = Arc::new(PendingJobs::default());
+    let entries = match fs::read_dir(src_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            report_dir_failure("read directory", src_dir, &e, observer, stats);
+            return;
+        }
+    };
+
+    // Purging is only safe when the source listing is complete
+    let mut listing_complete = true;
+
+    for entry in entries {
+        if queue.is_cancelled() {
+            break;
+        }
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                report_dir_failure("read directory", src_dir, &e, observer, stats);
+                listing_complete = false;
+                continue;
+            }
+        };
+        let path = entry.path();
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+
+        if path.is_file() {
+            if filter.includes_file(&file_name) {
+                let dst_path = dst_dir.join(&file_name);
+                if filter.excludes_file(&path) {
+                    observer.event(&CopyEvent::FileSkipped { src: &path, dst: &dst_path, reason: SkipReason::Excluded });
+                    stats.lock().unwrap().files_skipped += 1;
+                    continue;
+                }
+                if filter.ignores(&path, false) {
+                    observer.event(&CopyEvent::FileSkipped { src: &path, dst: &dst_path, reason: SkipReason::Ignored });
+                    stats.lock().unwrap().files_skipped += 1;
+                    continue;
+                }
+
+                src_entries.insert(file_name.clone());
+                if dst_path.is_dir() {
+                    observer.event(&CopyEvent::Mismatch { path: &dst_path, kind: EntryKind::File });
+                    stats.lock().unwrap().mismatches += 1;
+                    continue;
+                }
+
+                queue.submit(path, dst_path, &pending, options, observer, stats);
+            }
+        } else if path.is_dir() && options.recursive {
+            if filter.excludes_dir(&path) {
+                observer.event(&CopyEvent::DirectorySkipped { path: &path, reason: SkipReason::Excluded });
+                stats.lock().unwrap().dirs_skipped += 1;
+                continue;
+            }
+            if filter.ignores(&path, true) {
+                observer.event(&CopyEvent::DirectorySkipped { path: &path, reason: SkipReason::Ignored });
+                stats.lock().unwrap().dirs_skipped += 1;
+                continue;
+            }
+
+            src_entries.insert(file_name.clone());
+
+            let dst_subdir = dst_dir.join(&file_name);
+            if dst_subdir.is_file() {
+                observer.event(&CopyEvent::Mismatch { path: &dst_subdir, kind: EntryKind::Directory });
+                stats.lock().unwrap().mismatches += 1;
+                continue;
+            }
+
+            // /XL only updates what the destination has, it creates no new directories
+            if options.exclude_lonely && !dst_subdir.exists() {
+                observer.event(&CopyEvent::DirectorySkipped { path: &path, reason: SkipReason::Class(FileClass::Lonely) });
+                stats.lock().unwrap().dirs_skipped += 1;
+                continue;
+            }
+
+            // Skip empty directories if not including them
+            if !options.include_empty {
+                let is_empty = match path.read_dir() {
+                    Ok(mut dir_entries) => dir_entries.next().is_none(),
+                    Err(e) => {
+                        report_dir_failure("read directory", &path, &e, observer, stats);
+                        continue;
+                    }
+                };
+                if is_empty {
+                    observer.event(&CopyEvent::DirectorySkipped { path: &path, reason: SkipReason::Empty });
+                    stats.lock().unwrap().dirs_skipped += 1;
+                    continue;
+                }
+            }
+
+            // A directory that is moved as a whole to where nothing exists yet is just renamed;
+            // without /E its empty subdirectories have to stay behind, so it is copied file by file
+            if options.move_dirs
+                && options.include_empty
+                && !options.list_only
+                && !dst_subdir.exists()
+                && filter.takes_everything()
+                && options.attributes_add.is_empty()
+                && options.attributes_remove.is_empty()
+                && fs::metadata(&path).is_ok_and(|meta| same_device(&meta, &dst_subdir))
+            {
+                match fs::rename(&path, &dst_subdir) {
+                    Ok(_) => {
+                        observer.event(&CopyEvent::DirectoryMoved { src: &path, dst: &dst_subdir });
+                        stats.lock().unwrap().dirs_moved += 1;
+                        continue;
+                    }
+                    // Across file systems its files are copied one by one
+                    Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {}
+                    Err(e) => {
+                        report_dir_failure("move directory", &path, &e, observer, stats);
+                        continue;
+                    }
+                }
+            }
+
+            copy_directory(&path, &dst_subdir, &filter, options, observer, stats, queue);
+
+            // Move (delete source dir) if requested
+            if options.move_dirs && !options.list_only {
+                let is_empty = path.read_dir().is_ok_and(|mut dir_entries| dir_entries.next().is_none());
+                if is_empty {
+                    let _ = fs::remove_dir(&path);
+                }
+            }
+        }
+    }
+
+    // An interrupted directory was not fully copied, so it is neither purged nor checked for extras
+    if queue.is_cancelled() {
+        return;
+    }
+
+    // Purging and moving directories need every file job of this directory to be finished
+    if options.purge || options.mirror || options.move_dirs {
+        pending.wait();
+    }
+
+    // With /XX extras are neither reported nor purged
+    if options.exclude_extra {
+        return;
+    }
+
+    // Look for files/directories in destination that don't exist in source, purging them if requested
+    // In list-only mode the purge runs as well, it just reports what it would delete
+    let mut purge = options.purge || options.mirror;
+    if purge && !listing_complete {
+        observer.event(&CopyEvent::Notice {
+            message: &format!("Not purging {}, the source directory could not be read completely", dst_dir.display()),
+        });
+        purge = false;
+    }
+
+    if let Ok(entries) = fs::read_dir(dst_dir) {
+        for entry in entries.flatten() {
+            if queue.is_cancelled() {
+                break;
+            }
+
+            let path = entry.path();
+            let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+
+            // The marker of a mirrored destination and a backup directory inside it are not extras
+            if file_name == DESTINATION_MARKER || queue.backup().is_some_and(|backup| backup.is_backup_dir(&path)) {
+                continue;
+            }
+
+            // Keep the checkpoint and temporary files of a file that is still part of the source
+            if partial_target(&file_name).is_some_and(|target| src_entries.contains(target)) {
+                continue;
+            }
+
+            // Only entries the copy could have created are extras, everything else is left alone.
+            // Leftover checkpoint and temporary files go with the file they belong to.
+            let src_path = src_dir.join(partial_target(&file_name).unwrap_or(&file_name));
+            if !filter.in_purge_scope(&src_path, path.is_dir()) {
+                continue;
+            }
+
+            if !src_entries.contains(&file_name) {
+                if path.is_file() {
+                    stats.lock().unwrap().files_extra += 1;
+
+                    if !purge {
+                        observer.event(&CopyEvent::Extra { path: &path, kind: EntryKind::File });
+                        continue;
+                    }
+
+                    match remove_file(&path, options, observer, stats, queue.backup()) {
+                        Ok(_) => {}
+                        Err(e) => report_file_failure("remove file", &path, &e, observer, stats),
+                    }
+                } else if path.is_dir() {
+                    if options.recursive {
+                        stats.lock().unwrap().dirs_extra += 1;
+                    }
+
+                    if !purge {
+                        if options.recursive {
+                            observer.event(&CopyEvent::Extra { path: &path, kind: EntryKind::Directory });
+                        }
+                        continue;
+                    }
+
+                    match purge_directory(&path, &src_path, &filter, options, observer, stats, queue.backup()) {
+                        Ok(true) => report_removed_dir(&path, options, observer, stats),
+                        Ok(false) => observer.event(&CopyEvent::Notice {
+                            message: &format!("Keeping directory with entries outside the filter: {}", path.display()),
+                        }),
+                        Err(e) => report_dir_failure("remove directory", &path, &e, observer, stats),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Remove a destination file that is not in the source, or only report it in list-only mode
+fn remove_file(
+    path: &Path,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+    backup: Option<&Backup>,
+) -> io::Result<()> {
+    if !options.list_only {
+        if let Some(backup) = backup {
+            backup.save(path, observer, stats)?;
+        } else if options.shred_files {
+            securely_delete_file(path, observer)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+
+    observer.event(&CopyEvent::Removed {
+        path,
+        kind: EntryKind::File,
+        secure: options.shred_files,
+        list_only: options.list_only,
+    });
+    stats.lock().unwrap().files_removed += 1;
+    Ok(())
+}
+
+fn report_removed_dir(path: &Path, options: &CopyOptions, observer: &dyn CopyObserver, stats: &Mutex<Statistics>) {
+    observer.event(&CopyEvent::Removed {
+        path,
+        kind: EntryKind::Directory,
+        secure: options.shred_files,
+        list_only: options.list_only,
+    });
+    stats.lock().unwrap().dirs_removed += 1;
+}
+
+/// Number of files and directories below `path`, without following symbolic links
+///
+/// Given the `job`, the entries it owns and no purge deletes are left out: the destination
+/// marker and the backup directory.
+pub(crate) fn count_tree(path: &Path, job: Option<&JobContext>) -> io::Result<(usize, usize)> {
+    let (mut files, mut dirs) = (0, 0);
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let entry_path = entry.path();
+        if let Some(job) = job
+            && (entry.file_name() == DESTINATION_MARKER
+                || job.backup.is_some_and(|backup| backup.is_backup_dir(&entry_path)))
+        {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            let (nested_files, nested_dirs) = count_tree(&entry_path, job)?;
+            files += nested_files;
+            dirs += nested_dirs + 1;
+        } else {
+            files += 1;
+        }
+    }
+    Ok((files, dirs))
+}
+
+/// Remove an extra destination directory, returns whether it is gone
+///
+/// Files the filter rules out are kept, and so is every directory that still contains some.
+/// `src_path` is where the directory would be in the source tree. In list-only mode nothing is
+/// deleted, but every file and directory that would be is reported.
+pub(crate) fn purge_directory(
+    path: &Path,
+    src_path: &Path,
+    filter: &Filter,
+    options: &CopyOptions,
+    observer: &dyn CopyObserver,
+    stats: &Mutex<Statistics>,
+    backup: Option<&Backup>,
+) -> io::Result<bool> {
+    // Everything in the directory is in scope, no need to look at each entry
+    // unless each file has to be backed up
+    if filter.purges_everything() && !options.list_only && backup.is_none() {
+        // Counted beforehand, so that the totals match those of a /L run
+        let (files, dirs) = count_tree(path, None)?;
+        if options.shred_files {
+            secure_remove_dir_all(path, observer)?;
+        } else {
+            fs::remove_dir_all(path)?;
+        }
+        let mut stats = stats.lock().unwrap();
+        stats.files_removed += files;
+        stats.dirs_removed += dirs;
+        return Ok(true);
+    }
+
+    let mut keeps_entries = false;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let entry_path = entry.path();
+        let entry_src_path = src_path.join(entry.file_name());
+        let is_dir = entry_path.is_dir();
+        if !filter.in_purge_scope(&entry_src_path, is_dir) {
+            keeps_entries = true;
+            continue;
+        }
+
+        if is_dir {
+            if purge_directory(&entry_path, &entry_src_path, filter, options, observer, stats, backup)? {
+                report_removed_dir(&entry_path, options, observer, stats);
+            } else {
+                keeps_entries = true;
+            }
+        } else {
+            remove_file(&entry_path, options, observer, stats, backup)?;
+        }
+    }
+
+    if options.list_only {
+        return Ok(!keeps_entries);
+    }
+
+    if fs::read_dir(path)?.next().is_some() {
+        return Ok(false);
+    }
+    fs::remove_dir(path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::observer::ObserverList;
+    use crate::options::CopyOptionsBuilder;
+    use crate::test_dir::TestDir;
+
+    /// Copy `src` to `dst` in `dir` with the file patterns and options, and return the statistics
+    fn copy(dir: &TestDir, patterns: &[&str], options: &mut CopyOptionsBuilder) -> Statistics {
+        let options = options.threads(1).show_progress(false).build();
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        let filter = Filter::new(&dir.join("src"), &patterns, &options).unwrap();
+        let stats = Mutex::new(Statistics::default());
+        let cancel = CancellationToken::new();
+        let context = JobContext { cancel: &cancel, backup: None };
+
+        run_copy(&dir.join("src"), &dir.join("dst"), &filter, &options, &ObserverList(&[]), &stats, &context);
+        stats.into_inner().unwrap()
+    }
+
+    fn exists(dir: &TestDir, relative: &str) -> bool {
+        dir.join(relative).exists()
+    }
+
+    #[test]
+    fn purge_with_patterns_keeps_files_that_do_not_match() {
+        let dir = TestDir::new();
+        dir.create(&["src/new.jpg", "dst/old.jpg", "dst/notes.txt"]);
+
+        let stats = copy(&dir, &["*.jpg"], CopyOptions::builder().purge(true));
+        assert!(exists(&dir, "dst/new.jpg"));
+        assert!(!exists(&dir, "dst/old.jpg"));
+        assert!(exists(&dir, "dst/notes.txt"));
+        assert_eq!(stats.files_removed, 1);
+    }
+
+    #[test]
+    fn purge_all_deletes_files_that_do_not_match() {
+        let dir = TestDir::new();
+        dir.create(&["src/new.jpg", "dst/old.jpg", "dst/notes.txt"]);
+
+        let stats = copy(&dir, &["*.jpg"], CopyOptions::builder().purge(true).purge_all(true));
+        assert!(exists(&dir, "dst/new.jpg"));
+        assert!(!exists(&dir, "dst/old.jpg"));
+        assert!(!exists(&dir, "dst/notes.txt"));
+        assert_eq!(stats.files_removed, 2);
+    }
+
+    #[test]
+    fn purge_keeps_excluded_files_and_directories() {
+        let dir = TestDir::new();
+        dir.create(&["src/a.txt", "dst/debug.log", "dst/cache/blob", "dst/sub/cache/blob", "dst/sub/old.txt"]);
+
+        let mut options = CopyOptions::builder();
+        options.purge(true).recursive(true).include_empty(true).exclude_file("*.log").exclude_dir("cache");
+        let stats = copy(&dir, &[], &mut options);
+
+        assert!(exists(&dir, "dst/debug.log"));
+        assert!(exists(&dir, "dst/cache/blob"));
+        // An extra directory holding an excluded one stays, only what is in scope goes
+        assert!(exists(&dir, "dst/sub/cache/blob"));
+        assert!(!exists(&dir, "dst/sub/old.txt"));
+        assert_eq!((stats.files_removed, stats.dirs_removed), (1, 0));
+    }
+
+    #[test]
+    fn purge_without_recursion_leaves_subdirectories_alone() {
+        let dir = TestDir::new();
+        dir.create(&["src/a.txt", "src/sub/b.txt", "dst/old.txt", "dst/sub/old.txt", "dst/extra/old.txt"]);
+
+        let stats = copy(&dir, &[], CopyOptions::builder().purge(true));
+        assert!(exists(&dir, "dst/a.txt"));
+        assert!(!exists(&dir, "dst/old.txt"));
+        assert!(exists(&dir, "dst/sub/old.txt"));
+        assert!(exists(&dir, "dst/extra/old.txt"));
+        assert_eq!((stats.files_removed, stats.dirs_removed), (1, 0));
+    }
+}
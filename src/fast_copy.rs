@@ -0,0 +1,312 @@
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, Write};
+
+use crate::hash::Hasher;
+use crate::options::ChecksumAlgorithm;
+
+/// Size of the reads and writes when the data goes through userspace
+const BUFFER_SIZE: usize = 64 * 1024; // 64 KB buffer
+
+/// Blocks of zeros at least this large become holes with /SPARSE, the usual file system block size
+const SPARSE_BLOCK_SIZE: usize = 4096;
+
+/// Most bytes the kernel copies in one call, small enough to keep progress and cancellation responsive
+#[cfg(target_os = "linux")]
+const KERNEL_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// How a [`ChunkCopier`] moves data, from the fastest to the always available one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Method {
+    #[cfg(target_os = "linux")]
+    CopyFileRange,
+    #[cfg(target_os = "linux")]
+    SendFile,
+    Buffered,
+}
+
+/// Copies a file chunk by chunk from the current offset of the source to the current offset of
+/// the destination
+///
+/// On Linux the data is copied inside the kernel with `copy_file_range`, or `sendfile` where that
+/// is not supported, and only goes through a userspace buffer when neither works.
+pub(crate) struct ChunkCopier {
+    method: Method,
+    /// Skip blocks of zeros instead of writing them, which leaves holes in the destination
+    sparsify: bool,
+    buffer: Vec<u8>,
+    bytes_written: u64,
+    /// Hash of everything copied so far, for /V
+    hasher: Option<Hasher>,
+}
+
+impl ChunkCopier {
+    /// `kernel` allows copying inside the kernel; with `sparsify` every byte goes through the
+    /// buffer, since the kernel copy cannot look for zeros
+    pub(crate) fn new(kernel: bool, sparsify: bool) -> Self {
+        #[cfg(target_os = "linux")]
+        let method = if kernel && !sparsify { Method::CopyFileRange } else { Method::Buffered };
+        #[cfg(not(target_os = "linux"))]
+        let method = {
+            let _ = kernel;
+            Method::Buffered
+        };
+
+        ChunkCopier { method, sparsify, buffer: Vec::new(), bytes_written: 0, hasher: None }
+    }
+
+    /// Also hash the copied data; only the buffered copy sees it, so the copier must have been
+    /// created without kernel copies
+    pub(crate) fn hash(&mut self, algorithm: ChecksumAlgorithm) {
+        debug_assert_eq!(self.method, Method::Buffered);
+        self.hasher = Some(Hasher::new(algorithm));
+    }
+
+    /// Add `len` zeros to the hash, for a hole that is skipped instead of copied
+    pub(crate) fn hash_zeros(&mut self, mut len: u64) {
+        let Some(hasher) = &mut self.hasher else {
+            return;
+        };
+        let zeros = [0; SPARSE_BLOCK_SIZE];
+        while len > 0 {
+            let size = chunk_size(SPARSE_BLOCK_SIZE, len);
+            hasher.update(&zeros[..size]);
+            len -= size as u64;
+        }
+    }
+
+    /// Add the next `len` bytes of `src` to the hash without copying them, for the part of a
+    /// resumed file that an earlier attempt already copied
+    pub(crate) fn hash_source(&mut self, src: &mut File, len: u64) -> io::Result<()> {
+        let Some(hasher) = &mut self.hasher else {
+            return Ok(());
+        };
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let mut remaining = len;
+        while remaining > 0 {
+            let bytes_read = src.read(&mut buffer[..chunk_size(BUFFER_SIZE, remaining)])?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
+            remaining -= bytes_read as u64;
+        }
+        Ok(())
+    }
+
+    /// Hash of all data copied, `None` unless [`ChunkCopier::hash`] was called
+    pub(crate) fn finish_hash(&mut self) -> Option<Vec<u8>> {
+        self.hasher.take().map(Hasher::finish)
+    }
+
+    /// Bytes written to the destination so far, which leaves out the holes
+    pub(crate) fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Copy the next chunk of at most `limit` bytes, returns its size or 0 at the end of the source
+    pub(crate) fn copy_chunk(&mut self, src: &mut File, dst: &mut File, limit: u64) -> io::Result<usize> {
+        loop {
+            let result = match self.method {
+                #[cfg(target_os = "linux")]
+                Method::CopyFileRange => linux::copy_file_range(src, dst, chunk_size(KERNEL_CHUNK_SIZE, limit)),
+                #[cfg(target_os = "linux")]
+                Method::SendFile => linux::sendfile(src, dst, chunk_size(KERNEL_CHUNK_SIZE, limit)),
+                Method::Buffered => return self.copy_buffered(src, dst, limit),
+            };
+
+            match result {
+                Ok(0) => {
+                    // Some file systems report 0 instead of an error for data they cannot copy;
+                    // the buffered read tells the real end of the file apart from that
+                    self.method = Method::Buffered;
+                }
+                Ok(bytes) => {
+                    self.bytes_written += bytes as u64;
+                    return Ok(bytes);
+                }
+                #[cfg(target_os = "linux")]
+                Err(e) if linux::is_unsupported(&e) => self.method = self.fallback(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn fallback(&self) -> Method {
+        match self.method {
+            Method::CopyFileRange => Method::SendFile,
+            _ => Method::Buffered,
+        }
+    }
+
+    fn copy_buffered(&mut self, src: &mut File, dst: &mut File, limit: u64) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            self.buffer = vec![0; BUFFER_SIZE];
+        }
+
+        let bytes_read = src.read(&mut self.buffer[..chunk_size(BUFFER_SIZE, limit)])?;
+        let data = &self.buffer[..bytes_read];
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(data);
+        }
+        if !self.sparsify {
+            dst.write_all(data)?;
+            self.bytes_written += bytes_read as u64;
+            return Ok(bytes_read);
+        }
+
+        // Write the runs of data, seek over the runs of zero blocks
+        let mut start = 0;
+        while start < data.len() {
+            let zeros = is_zero_block(&data[start..]);
+            let mut end = start;
+            while end < data.len() && is_zero_block(&data[end..]) == zeros {
+                end = (end + SPARSE_BLOCK_SIZE).min(data.len());
+            }
+
+            if zeros {
+                dst.seek(io::SeekFrom::Current((end - start) as i64))?;
+            } else {
+                dst.write_all(&data[start..end])?;
+                self.bytes_written += (end - start) as u64;
+            }
+            start = end;
+        }
+        Ok(bytes_read)
+    }
+}
+
+fn chunk_size(size: usize, limit: u64) -> usize {
+    usize::try_from(limit).map_or(size, |limit| limit.min(size))
+}
+
+/// Whether the block at the start of `data` only contains zeros
+fn is_zero_block(data: &[u8]) -> bool {
+    data[..data.len().min(SPARSE_BLOCK_SIZE)].iter().all(|&byte| byte == 0)
+}
+
+/// Whether a file has holes, i.e. takes less space on disk than its size
+#[cfg(unix)]
+pub(crate) fn is_sparse(meta: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    meta.blocks() * 512 < meta.len()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_sparse(_meta: &Metadata) -> bool {
+    false
+}
+
+/// The next range of data at or after `offset`, as start and end offsets, or `None` if the rest
+/// of the file is a hole; leaves the file offset at the start of the data
+#[cfg(target_os = "linux")]
+pub(crate) fn next_data(file: &mut File, offset: u64) -> io::Result<Option<(u64, u64)>> {
+    linux::next_data(file, offset)
+}
+
+/// Without `SEEK_DATA` the whole file counts as data
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn next_data(file: &mut File, offset: u64) -> io::Result<Option<(u64, u64)>> {
+    file.seek(io::SeekFrom::Start(offset))?;
+    Ok(Some((offset, u64::MAX)))
+}
+
+/// Make `file` at least `len` bytes long, which turns skipped trailing zeros into a hole
+pub(crate) fn extend_to(file: &File, len: u64) -> io::Result<()> {
+    if file.metadata()?.len() < len {
+        file.set_len(len)?;
+    }
+    Ok(())
+}
+
+/// Drop the cached pages of a file that was synced to disk, so that reading it again reads the
+/// disk rather than memory; a hint that may do nothing
+#[cfg(target_os = "linux")]
+pub(crate) fn drop_cache(file: &File) {
+    linux::drop_cache(file);
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn drop_cache(_file: &File) {}
+
+/// Make `dst` share the data of `src` with a copy-on-write reflink (/REFLINK)
+///
+/// Returns `false` if the file system cannot do it, e.g. ext4 or two different file systems.
+#[cfg(target_os = "linux")]
+pub(crate) fn reflink(src: &File, dst: &File) -> io::Result<bool> {
+    linux::ficlone(src, dst)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn reflink(_src: &File, _dst: &File) -> io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+
+    fn result(ret: libc::ssize_t) -> io::Result<usize> {
+        if ret < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret as usize)
+        }
+    }
+
+    pub(super) fn copy_file_range(src: &File, dst: &File, len: usize) -> io::Result<usize> {
+        // Null offsets use and advance the file offsets, like read and write do
+        let ret = unsafe {
+            libc::copy_file_range(src.as_raw_fd(), ptr::null_mut(), dst.as_raw_fd(), ptr::null_mut(), len, 0)
+        };
+        result(ret)
+    }
+
+    pub(super) fn sendfile(src: &File, dst: &File, len: usize) -> io::Result<usize> {
+        let ret = unsafe { libc::sendfile(dst.as_raw_fd(), src.as_raw_fd(), ptr::null_mut(), len) };
+        result(ret)
+    }
+
+    pub(super) fn next_data(file: &mut File, offset: u64) -> io::Result<Option<(u64, u64)>> {
+        let seek = |offset: u64, whence: libc::c_int| {
+            let ret = unsafe { libc::lseek(file.as_raw_fd(), offset as libc::off_t, whence) };
+            if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(ret as u64) }
+        };
+
+        let start = match seek(offset, libc::SEEK_DATA) {
+            Ok(start) => start,
+            // No data after the offset, only a hole up to the end of the file
+            Err(e) if e.raw_os_error() == Some(libc::ENXIO) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let end = seek(start, libc::SEEK_HOLE)?;
+        seek(start, libc::SEEK_SET)?;
+        Ok(Some((start, end)))
+    }
+
+    pub(super) fn drop_cache(file: &File) {
+        unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    }
+
+    pub(super) fn ficlone(src: &File, dst: &File) -> io::Result<bool> {
+        let ret = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) };
+        if ret == 0 {
+            return Ok(true);
+        }
+
+        let e = io::Error::last_os_error();
+        if is_unsupported(&e) { Ok(false) } else { Err(e) }
+    }
+
+    /// Errors that mean the kernel cannot copy these two files this way, rather than an I/O error
+    pub(super) fn is_unsupported(e: &io::Error) -> bool {
+        matches!(
+            e.raw_os_error(),
+            Some(libc::ENOSYS | libc::EXDEV | libc::EINVAL | libc::EOPNOTSUPP | libc::ENOTTY | libc::EPERM | libc::EBADF)
+        )
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::options::ChecksumAlgorithm;
+
+/// Size of the reads while hashing a file
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Incremental hash of a file's contents with one of the [`ChecksumAlgorithm`]s
+pub(crate) enum Hasher {
+    Xxh3(Box<Xxh3>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Xxh3 => Hasher::Xxh3(Box::default()),
+            ChecksumAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Xxh3(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        match self {
+            Hasher::Xxh3(hasher) => hasher.digest128().to_be_bytes().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+/// Hash of the contents of the file at `path`
+pub(crate) fn hash_file(path: &Path, algorithm: ChecksumAlgorithm) -> io::Result<Vec<u8>> {
+    hash_reader(File::open(path)?, algorithm)
+}
+
+/// Hash of everything `file` returns from its current position on
+pub(crate) fn hash_reader(mut file: impl Read, algorithm: ChecksumAlgorithm) -> io::Result<Vec<u8>> {
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = vec![0; BUFFER_SIZE];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Whether two files have the same contents, compared by their SHA-256
+pub(crate) fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    Ok(hash_file(a, ChecksumAlgorithm::Sha256)? == hash_file(b, ChecksumAlgorithm::Sha256)?)
+}
+
+/// Whether two files have the same contents, compared by hashes that are computed side by side
+/// so that reading both files overlaps
+pub(crate) fn same_checksum(a: &Path, b: &Path, algorithm: ChecksumAlgorithm) -> io::Result<bool> {
+    thread::scope(|scope| {
+        let other = scope.spawn(|| hash_file(b, algorithm));
+        let hash = hash_file(a, algorithm)?;
+        let other = other.join().unwrap_or_else(|_| Err(io::Error::other("hashing thread panicked")))?;
+        Ok(hash == other)
+    })
+}
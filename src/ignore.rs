@@ -0,0 +1,230 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::pattern::{relative_path, Glob};
+
+/// Ignore files read in every directory, from lowest to highest precedence
+pub(crate) const IGNORE_FILE_NAMES: [&str; 3] = [".gitignore", ".ignore", ".colemenignore"];
+
+/// One line of an ignore file
+#[derive(Debug)]
+struct IgnoreRule {
+    glob: Glob,
+    /// `!pattern`, re-includes what an earlier rule ignored
+    negated: bool,
+    /// `pattern/`, only matches directories
+    dir_only: bool,
+    /// The pattern contains a `/`, so it is matched against the path relative to the ignore file
+    anchored: bool,
+}
+
+/// The rules of all ignore files of one directory
+#[derive(Debug)]
+pub(crate) struct IgnoreRules {
+    base: PathBuf,
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    /// Read the ignore files of `dir`, returns `None` if it has none
+    pub(crate) fn load(dir: &Path) -> io::Result<Option<Self>> {
+        let mut rules = Vec::new();
+        let mut found = false;
+
+        for name in IGNORE_FILE_NAMES {
+            let contents = match fs::read_to_string(dir.join(name)) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            found = true;
+
+            for line in contents.lines() {
+                if let Some(rule) = parse_line(line) {
+                    rules.push(rule);
+                }
+            }
+        }
+
+        Ok(found.then(|| IgnoreRules { base: dir.to_path_buf(), rules }))
+    }
+
+    /// `Some(true)` if the last rule matching `path` ignores it, `Some(false)` if it re-includes
+    /// it, `None` if no rule matches
+    pub(crate) fn decide(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let relative_path = relative_path(&self.base, path);
+
+        self.rules.iter().rev().find_map(|rule| {
+            if rule.dir_only && !is_dir {
+                return None;
+            }
+            let text = if rule.anchored { relative_path.as_str() } else { &name };
+            rule.glob.matches(text).then_some(!rule.negated)
+        })
+    }
+}
+
+/// Parse one line of an ignore file with gitignore semantics
+fn parse_line(line: &str) -> Option<IgnoreRule> {
+    // Trailing spaces are ignored unless escaped with a backslash
+    let mut line = line.trim_end_matches(['\r', '\n']);
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line = &line[..line.len() - 1];
+    }
+
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+
+    // A slash at the start or in the middle ties the pattern to the directory of the ignore file
+    let anchored = line.contains('/');
+    let line = line.strip_prefix('/').unwrap_or(line);
+    if line.is_empty() {
+        return None;
+    }
+
+    // Like git, a pattern that cannot be compiled matches nothing
+    let glob = Glob::new(&unescape(line)).ok()?;
+    Some(IgnoreRule { glob, negated, dir_only, anchored })
+}
+
+/// Turn gitignore's backslash escapes, and the braces gitignore does not expand, into something
+/// the glob matches literally
+fn unescape(pattern: &str) -> String {
+    let mut result = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                // Characters that are special to the glob are matched through a class
+                Some(escaped @ ('*' | '?' | '[' | '{' | '}' | ',')) => {
+                    result.push('[');
+                    result.push(escaped);
+                    result.push(']');
+                }
+                Some(escaped) => result.push(escaped),
+                None => {}
+            },
+            '{' | '}' => {
+                result.push('[');
+                result.push(c);
+                result.push(']');
+            }
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::CopyOptions;
+    use crate::pattern::Filter;
+    use crate::test_dir::TestDir;
+
+    /// The rules of an ignore file in /src with these lines
+    fn rules(lines: &str) -> IgnoreRules {
+        IgnoreRules { base: PathBuf::from("/src"), rules: lines.lines().filter_map(parse_line).collect() }
+    }
+
+    #[test]
+    fn blank_lines_and_comments_are_skipped() {
+        assert!(parse_line("").is_none());
+        assert!(parse_line("   ").is_none());
+        assert!(parse_line("# build output").is_none());
+        assert!(parse_line("/").is_none());
+    }
+
+    #[test]
+    fn line_flags_are_parsed() {
+        let rule = parse_line("!keep.log").unwrap();
+        assert!(rule.negated && !rule.dir_only && !rule.anchored);
+
+        let rule = parse_line("build/").unwrap();
+        assert!(rule.dir_only && !rule.anchored);
+
+        let rule = parse_line("/target").unwrap();
+        assert!(rule.anchored && !rule.dir_only);
+
+        let rule = parse_line("docs/*.md").unwrap();
+        assert!(rule.anchored);
+    }
+
+    #[test]
+    fn trailing_spaces_are_trimmed_unless_escaped() {
+        assert!(parse_line("notes.txt  ").unwrap().glob.matches("notes.txt"));
+        assert!(parse_line("notes\\ ").unwrap().glob.matches("notes "));
+    }
+
+    #[test]
+    fn escapes_and_braces_are_literal() {
+        assert_eq!(unescape("\\*.txt"), "[*].txt");
+        assert_eq!(unescape("\\#notes"), "#notes");
+        assert_eq!(unescape("{a,b}"), "[{]a,b[}]");
+        assert_eq!(unescape("trailing\\"), "trailing");
+        assert!(parse_line("\\!important").unwrap().glob.matches("!important"));
+    }
+
+    #[test]
+    fn negation_re_includes_what_an_earlier_rule_ignored() {
+        let rules = rules("*.log\n!keep.log");
+        assert_eq!(rules.decide(Path::new("/src/debug.log"), false), Some(true));
+        assert_eq!(rules.decide(Path::new("/src/keep.log"), false), Some(false));
+        assert_eq!(rules.decide(Path::new("/src/main.rs"), false), None);
+    }
+
+    #[test]
+    fn anchored_patterns_match_the_relative_path() {
+        let rules = rules("/target\ndocs/*.md");
+        assert_eq!(rules.decide(Path::new("/src/target"), true), Some(true));
+        assert_eq!(rules.decide(Path::new("/src/sub/target"), true), None);
+        assert_eq!(rules.decide(Path::new("/src/docs/readme.md"), false), Some(true));
+        assert_eq!(rules.decide(Path::new("/src/sub/docs/readme.md"), false), None);
+    }
+
+    #[test]
+    fn unanchored_patterns_match_the_name_at_any_depth() {
+        let rules = rules("*.tmp");
+        assert_eq!(rules.decide(Path::new("/src/a/b/cache.tmp"), false), Some(true));
+    }
+
+    #[test]
+    fn dir_only_patterns_skip_files() {
+        let rules = rules("build/");
+        assert_eq!(rules.decide(Path::new("/src/build"), true), Some(true));
+        assert_eq!(rules.decide(Path::new("/src/build"), false), None);
+    }
+
+    #[test]
+    fn deeper_ignore_files_override_the_ones_above() {
+        let dir = TestDir::new();
+        let (root, logs) = (dir.join("src"), dir.join("src/logs"));
+        fs::create_dir_all(&logs).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(logs.join(".gitignore"), "!keep.log\n").unwrap();
+
+        let options = CopyOptions::builder().use_ignore_files(true).build();
+        let filter = Filter::new(&root, &[], &options).unwrap();
+        let filter = filter.enter(&root).unwrap();
+        let nested = filter.enter(&logs).unwrap();
+        let ignored = |filter: &Filter, name: &str| filter.ignores(&logs.join(name), false);
+
+        assert!(ignored(&filter, "keep.log"));
+        assert!(!ignored(&nested, "keep.log"));
+        assert!(ignored(&nested, "debug.log"));
+    }
+}
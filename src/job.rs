@@ -0,0 +1,154 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::backup::Backup;
+use crate::cancel::CancellationToken;
+use crate::engine::{run_copy, JobContext};
+use crate::observer::{CopyEvent, CopyObserver, ObserverList};
+use crate::options::CopyOptions;
+use crate::pattern::Filter;
+use crate::safety::{check_delete_limit, check_purge, mark_destination};
+use crate::stats::Statistics;
+
+/// A copy of one directory tree to another
+///
+/// ```no_run
+/// use colemen_copy::{ConsoleObserver, CopyJob, CopyOptions};
+///
+/// let options = CopyOptions::builder().recursive(true).threads(8).build();
+/// let mut job = CopyJob::new("/data/in", "/data/out", options.clone());
+/// job.observer(ConsoleObserver::new(&options)?);
+/// let stats = job.run()?;
+/// println!("{} files copied", stats.files_copied);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct CopyJob {
+    source: PathBuf,
+    destination: PathBuf,
+    file_patterns: Vec<String>,
+    options: CopyOptions,
+    observers: Vec<Arc<dyn CopyObserver>>,
+    cancel: CancellationToken,
+}
+
+impl CopyJob {
+    pub fn new(source: impl Into<PathBuf>, destination: impl Into<PathBuf>, options: CopyOptions) -> Self {
+        CopyJob {
+            source: source.into(),
+            destination: destination.into(),
+            file_patterns: Vec::new(),
+            options,
+            observers: Vec::new(),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// Only copy files whose name matches the pattern
+    ///
+    /// Can be called several times, a file is copied if its name matches any of the patterns.
+    /// Patterns are shell style wildcards: `*`, `?`, classes like `[0-9]` and alternatives like
+    /// `{jpg,png}`.
+    pub fn file_pattern(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.file_patterns.push(pattern.into());
+        self
+    }
+
+    /// Send every event of the job to `observer`
+    ///
+    /// Without observers a job runs silently; attach a [`ConsoleObserver`](crate::ConsoleObserver)
+    /// for the command line output and the `/LOG` file.
+    pub fn observer(&mut self, observer: impl CopyObserver + 'static) -> &mut Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Stop the job early once `token` is cancelled
+    pub fn cancel_token(&mut self, token: CancellationToken) -> &mut Self {
+        self.cancel = token;
+        self
+    }
+
+    /// Run the copy and return what happened
+    ///
+    /// Errors on single files or directories are counted in the returned [`Statistics`]; an `Err`
+    /// means the job could not run at all (invalid options or patterns, missing source or unusable destination),
+    /// or that a purge was refused because it looked like a mistake (see /MAXDEL and /FORCE).
+    pub fn run(&self) -> io::Result<Statistics> {
+        let source_path = self.source.as_path();
+        let dest_path = self.destination.as_path();
+        let options = &self.options;
+
+        options.validate()?;
+        let filter = Filter::new(source_path, &self.file_patterns, options)?;
+
+        // Check if source directory exists
+        if !source_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Source directory does not exist: {}", source_path.display()),
+            ));
+        }
+
+        let start_time = SystemTime::now();
+        let backup = match &options.backup_dir {
+            Some(backup_dir) if !options.list_only => Some(Backup::new(backup_dir, dest_path, start_time)),
+            _ => None,
+        };
+        let context = JobContext { cancel: &self.cancel, backup: backup.as_ref() };
+
+        // Guard against wiping the wrong directory before anything gets deleted; /XX deletes nothing
+        let purge = (options.purge || options.mirror) && !options.list_only && !options.exclude_extra;
+        if purge {
+            check_purge(source_path, dest_path, options)?;
+            check_delete_limit(source_path, dest_path, &filter, options, &context)?;
+        }
+
+        let observer = ObserverList(&self.observers);
+
+        observer.event(&CopyEvent::JobStarted {
+            source: source_path,
+            destination: dest_path,
+            file_patterns: &self.file_patterns,
+            options,
+        });
+
+        // Create destination directory if it doesn't exist
+        if !dest_path.exists() {
+            observer.event(&CopyEvent::DirectoryCreated { path: dest_path, list_only: options.list_only });
+            if !options.list_only {
+                fs::create_dir_all(dest_path)?;
+            }
+        }
+        if purge {
+            mark_destination(dest_path)?;
+        }
+
+        // Perform the copy operation
+        let stats = Mutex::new(Statistics::default());
+
+        run_copy(source_path, dest_path, &filter, options, &observer, &stats, &context);
+
+        if let Some(backup) = &backup
+            && let Err(e) = backup.prune(options, &observer)
+        {
+            observer.event(&CopyEvent::Notice { message: &format!("Failed to remove old backups: {}", e) });
+        }
+
+        let mut stats = stats.into_inner().unwrap();
+        stats.interrupted = self.cancel.is_cancelled();
+
+        let elapsed = SystemTime::now().duration_since(start_time).unwrap_or(Duration::from_secs(0));
+        observer.event(&CopyEvent::JobFinished {
+            source: source_path,
+            destination: dest_path,
+            stats: &stats,
+            elapsed,
+        });
+
+        Ok(stats)
+    }
+}
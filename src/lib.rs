@@ -0,0 +1,34 @@
+//! ColemenCopy - A cross-platform alternative to Robocopy
+//!
+//! The copy engine behind the `colemen_copy` command line tool. Build a [`CopyOptions`] value,
+//! hand it to a [`CopyJob`] and call [`CopyJob::run`] to get the [`Statistics`] of the run.
+//! Progress is reported as [`CopyEvent`]s to any [`CopyObserver`] attached to the job.
+
+mod backup;
+mod cancel;
+mod checkpoint;
+mod class;
+mod copy;
+mod engine;
+mod fast_copy;
+mod hash;
+mod ignore;
+mod job;
+mod observer;
+mod options;
+mod pattern;
+mod safety;
+mod shred;
+mod stats;
+#[cfg(test)]
+mod test_dir;
+
+pub use cancel::CancellationToken;
+pub use class::FileClass;
+pub use job::CopyJob;
+pub use observer::{ConsoleObserver, CopyEvent, CopyObserver, EntryKind, SkipReason};
+pub use options::{ChecksumAlgorithm, CopyOptions, CopyOptionsBuilder, DeleteLimit};
+pub use stats::{
+    Statistics, EXIT_EXTRAS, EXIT_FAILURES, EXIT_FATAL, EXIT_FILES_COPIED, EXIT_INTERRUPTED,
+    EXIT_MISMATCHES,
+};
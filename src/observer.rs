@@ -0,0 +1,380 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::class::FileClass;
+use crate::options::{format_options, CopyOptions};
+use crate::stats::Statistics;
+
+/// Whether an event is about a file or a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// Why a file or directory was not copied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Files of this class are not copied, like Same files by default or Older files with /XO
+    Class(FileClass),
+    /// The directory is empty and empty directories are not copied
+    Empty,
+    /// The entry matches an exclusion (/XF or /XD)
+    Excluded,
+    /// An ignore file (/IGNOREFILES) rules out the entry
+    Ignored,
+}
+
+/// Something that happened while a copy job was running
+#[derive(Debug)]
+pub enum CopyEvent<'a> {
+    JobStarted {
+        source: &'a Path,
+        destination: &'a Path,
+        file_patterns: &'a [String],
+        options: &'a CopyOptions,
+    },
+    JobFinished {
+        source: &'a Path,
+        destination: &'a Path,
+        stats: &'a Statistics,
+        elapsed: Duration,
+    },
+    /// A destination directory was created (or would be, in list-only mode)
+    DirectoryCreated { path: &'a Path, list_only: bool },
+    DirectorySkipped { path: &'a Path, reason: SkipReason },
+    /// A whole directory was moved by renaming it, its contents are not reported one by one
+    DirectoryMoved { src: &'a Path, dst: &'a Path },
+    DirectoryFailed { path: &'a Path, action: &'a str, error: &'a io::Error },
+    /// A file is about to be copied (or would be, in list-only mode)
+    FileStarted { src: &'a Path, dst: &'a Path, size: u64, class: FileClass, list_only: bool },
+    /// A restartable copy continues from an earlier, interrupted attempt
+    FileResumed { dst: &'a Path, offset: u64 },
+    FileProgress { dst: &'a Path, bytes_copied: u64, total_bytes: u64 },
+    FileSkipped { src: &'a Path, dst: &'a Path, reason: SkipReason },
+    FileCopied { src: &'a Path, dst: &'a Path, bytes: u64 },
+    /// A file was moved by renaming it, source and destination are on the same file system
+    FileMoved { src: &'a Path, dst: &'a Path },
+    FileRetried {
+        src: &'a Path,
+        dst: &'a Path,
+        attempt: usize,
+        retries: usize,
+        delay: Duration,
+        error: &'a io::Error,
+    },
+    FileFailed { path: &'a Path, action: &'a str, error: &'a io::Error },
+    /// The copy of a file was aborted because the job was cancelled; a resumable partial file is
+    /// kept for `/Z`, anything else written so far was removed
+    FileCancelled { src: &'a Path, dst: &'a Path, resumable: bool },
+    /// A destination entry that does not exist in the source was deleted (or would be, in
+    /// list-only mode)
+    Removed { path: &'a Path, kind: EntryKind, secure: bool, list_only: bool },
+    /// A destination file was moved to the backup directory before being deleted or overwritten
+    BackedUp { path: &'a Path, backup: &'a Path },
+    /// A destination entry does not exist in the source and was left alone
+    Extra { path: &'a Path, kind: EntryKind },
+    /// The source entry is of `kind`, but the destination has the other kind under the same name
+    Mismatch { path: &'a Path, kind: EntryKind },
+    /// Any other information worth reporting
+    Notice { message: &'a str },
+}
+
+/// Receives every event of a copy job
+///
+/// Events are delivered from the worker threads, so implementations must be thread safe.
+pub trait CopyObserver: Send + Sync {
+    fn event(&self, event: &CopyEvent);
+}
+
+impl<T: CopyObserver + ?Sized> CopyObserver for Arc<T> {
+    fn event(&self, event: &CopyEvent) {
+        (**self).event(event);
+    }
+}
+
+/// Forwards events to every observer attached to a job
+pub(crate) struct ObserverList<'a>(pub(crate) &'a [Arc<dyn CopyObserver>]);
+
+impl CopyObserver for ObserverList<'_> {
+    fn event(&self, event: &CopyEvent) {
+        for observer in self.0 {
+            observer.event(event);
+        }
+    }
+}
+
+/// The classic colemen_copy output: messages on the console and in the `/LOG` file
+pub struct ConsoleObserver {
+    log_file: Mutex<Option<File>>,
+    show_progress: bool,
+    log_file_names: bool,
+    // Last percentage shown for the file being copied, if progress was shown at all
+    last_progress: Mutex<Option<u64>>,
+}
+
+impl ConsoleObserver {
+    /// Create the observer for a job, opening its log file if one is configured
+    pub fn new(options: &CopyOptions) -> io::Result<Self> {
+        let log_file = if let Some(log_path) = &options.log_file {
+            Some(File::create(log_path)?)
+        } else {
+            None
+        };
+
+        Ok(ConsoleObserver {
+            log_file: Mutex::new(log_file),
+            // Workers would garble the progress line, so it is only shown when copying on a single thread
+            show_progress: options.show_progress && options.threads == 1,
+            log_file_names: options.log_file_names,
+            last_progress: Mutex::new(None),
+        })
+    }
+
+    fn log_message(&self, message: &str) {
+        // Holding the log lock while printing keeps lines from different workers intact
+        let mut log_file = self.log_file.lock().unwrap();
+        println!("{}", message);
+        if let Some(log) = log_file.as_mut() {
+            let _ = writeln!(log, "{}", message);
+        }
+    }
+}
+
+impl CopyObserver for ConsoleObserver {
+    fn event(&self, event: &CopyEvent) {
+        match *event {
+            CopyEvent::JobStarted { source, destination, file_patterns, options } => {
+                let start_msg = format!(
+                    "-------------------------------------------------------------------------------\n\
+                     ColemenCopy - Started: {}\n\
+                     Source: {}\n\
+                     Destination: {}\n\
+                     Pattern: {}\n\
+                     Options: {}\n\
+                     -------------------------------------------------------------------------------\n",
+                    format_time(SystemTime::now()),
+                    source.display(),
+                    destination.display(),
+                    if file_patterns.is_empty() { "*.*".to_string() } else { file_patterns.join(" ") },
+                    format_options(options)
+                );
+                self.log_message(&start_msg);
+            }
+            CopyEvent::JobFinished { source, destination, stats, elapsed } => {
+                let summary = format!(
+                    "-------------------------------------------------------------------------------\n\
+                     ColemenCopy - {}: {}\n\
+                     Source: {}\n\
+                     Destination: {}\n\n\
+                     Statistics:\n\
+                         Directories: {}\n\
+                         Files: {}\n\
+                         Bytes: {}\n\
+                         Bytes written: {}\n\
+                         Directories skipped: {}\n\
+                         Files skipped: {}\n\
+                         Files failed: {}\n\
+                         Directories failed: {}\n\
+                         Directories removed: {}\n\
+                         Files removed: {}\n\
+                         Extra directories: {}\n\
+                         Extra files: {}\n\
+                         New files: {}\n\
+                         Newer files: {}\n\
+                         Older files: {}\n\
+                         Changed files: {}\n\
+                         Same files: {}\n\
+                         Tweaked files: {}\n\
+                         Mismatches: {}\n\
+                         Verification failures: {}\n\
+                         Files checksummed: {}\n\
+                         Bytes checksummed: {}\n\
+                         Files moved: {}\n\
+                         Directories moved: {}\n\
+                         Files backed up: {}\n\n\
+                     {}\
+                     Elapsed time: {} seconds\n\
+                     Exit code: {}\n\
+                     -------------------------------------------------------------------------------\n",
+                    if stats.interrupted { "Interrupted" } else { "Finished" },
+                    format_time(SystemTime::now()),
+                    source.display(),
+                    destination.display(),
+                    stats.dirs_created,
+                    stats.files_copied,
+                    stats.bytes_copied,
+                    stats.bytes_written,
+                    stats.dirs_skipped,
+                    stats.files_skipped,
+                    stats.files_failed,
+                    stats.dirs_failed,
+                    stats.dirs_removed,
+                    stats.files_removed,
+                    stats.dirs_extra,
+                    stats.files_extra,
+                    stats.files_lonely,
+                    stats.files_newer,
+                    stats.files_older,
+                    stats.files_changed,
+                    stats.files_same,
+                    stats.files_tweaked,
+                    stats.mismatches,
+                    stats.files_verify_failed,
+                    stats.files_checksummed,
+                    stats.bytes_checksummed,
+                    stats.files_moved,
+                    stats.dirs_moved,
+                    stats.files_backed_up,
+                    format_failures(&stats.failures),
+                    elapsed.as_secs(),
+                    stats.exit_code()
+                );
+                self.log_message(&summary);
+            }
+            CopyEvent::DirectoryCreated { path, list_only } => {
+                if list_only {
+                    self.log_message(&format!("Would create directory: {}", path.display()));
+                } else {
+                    self.log_message(&format!("Creating directory: {}", path.display()));
+                }
+            }
+            CopyEvent::DirectorySkipped { path, reason } => {
+                if self.log_file_names {
+                    let message = match reason {
+                        SkipReason::Excluded => "Skipping excluded directory",
+                        SkipReason::Ignored => "Skipping ignored directory",
+                        SkipReason::Class(_) => "Skipping new directory",
+                        SkipReason::Empty => "Skipping empty directory",
+                    };
+                    self.log_message(&format!("{}: {}", message, path.display()));
+                }
+            }
+            CopyEvent::DirectoryMoved { src, dst } => {
+                self.log_message(&format!("Moving directory: {} -> {}", src.display(), dst.display()));
+            }
+            CopyEvent::DirectoryFailed { path, action, error }
+            | CopyEvent::FileFailed { path, action, error } => {
+                self.log_message(&format!("ERROR: Failed to {} {}, Error: {}", action, path.display(), error));
+            }
+            CopyEvent::FileStarted { src, dst, class, list_only, .. } => {
+                *self.last_progress.lock().unwrap() = None;
+                if list_only {
+                    self.log_message(&format!("{}, would copy: {} -> {}", class.label(), src.display(), dst.display()));
+                } else if self.log_file_names {
+                    self.log_message(&format!("{}: {} -> {}", class.label(), src.display(), dst.display()));
+                }
+            }
+            CopyEvent::FileResumed { dst, offset } => {
+                self.log_message(&format!("Resuming at byte {}: {}", offset, dst.display()));
+            }
+            CopyEvent::FileProgress { bytes_copied, total_bytes, .. } => {
+                if self.show_progress && total_bytes > 0 {
+                    let progress = (bytes_copied * 100) / total_bytes;
+                    let mut last_progress = self.last_progress.lock().unwrap();
+                    if last_progress.is_none_or(|last| progress > last) {
+                        print!("\rCopying: {}% complete", progress);
+                        let _ = io::stdout().flush();
+                        *last_progress = Some(progress);
+                    }
+                }
+            }
+            CopyEvent::FileSkipped { src, dst, reason } => {
+                if self.log_file_names {
+                    match reason {
+                        SkipReason::Excluded => self.log_message(&format!("Skipping excluded file: {}", src.display())),
+                        SkipReason::Ignored => self.log_message(&format!("Skipping ignored file: {}", src.display())),
+                        SkipReason::Class(class) => {
+                            self.log_message(&format!("{}, skipped: {}", class.label(), dst.display()))
+                        }
+                        SkipReason::Empty => {}
+                    }
+                }
+            }
+            CopyEvent::FileCopied { .. } => {
+                if self.last_progress.lock().unwrap().take().is_some() {
+                    println!("\rCopying: 100% complete");
+                }
+            }
+            CopyEvent::FileMoved { src, dst } => {
+                if self.log_file_names {
+                    self.log_message(&format!("Moving file: {} -> {}", src.display(), dst.display()));
+                }
+            }
+            CopyEvent::FileRetried { src, dst, attempt, retries, delay, error } => {
+                self.log_message(&format!("Retry {} of {} in {:.1} seconds: {} -> {}, Error: {}",
+                    attempt, retries, delay.as_secs_f64(), src.display(), dst.display(), error));
+            }
+            CopyEvent::FileCancelled { dst, resumable, .. } => {
+                // Finish the progress line of the aborted file
+                if self.last_progress.lock().unwrap().take().is_some() {
+                    println!();
+                }
+                if resumable {
+                    self.log_message(&format!("Cancelled, the partial file can be resumed: {}", dst.display()));
+                } else {
+                    self.log_message(&format!("Cancelled, removed the partial file: {}", dst.display()));
+                }
+            }
+            CopyEvent::Removed { path, kind, secure, list_only } => {
+                let message = match (kind, secure, list_only) {
+                    (EntryKind::File, _, true) => "Would remove file",
+                    (EntryKind::Directory, _, true) => "Would remove directory",
+                    (EntryKind::File, false, false) => "Removing file",
+                    (EntryKind::File, true, false) => "Securely removing file",
+                    (EntryKind::Directory, false, false) => "Removing directory",
+                    (EntryKind::Directory, true, false) => "Securely removing directory",
+                };
+                self.log_message(&format!("{}: {}", message, path.display()));
+            }
+            CopyEvent::BackedUp { path, backup } => {
+                if self.log_file_names {
+                    self.log_message(&format!("Backing up file: {} -> {}", path.display(), backup.display()));
+                }
+            }
+            CopyEvent::Extra { path, kind } => {
+                if self.log_file_names {
+                    let message = match kind {
+                        EntryKind::File => FileClass::Extra.label(),
+                        EntryKind::Directory => "*EXTRA Dir",
+                    };
+                    self.log_message(&format!("{}: {}", message, path.display()));
+                }
+            }
+            CopyEvent::Mismatch { path, kind } => {
+                let message = match kind {
+                    EntryKind::File => "Mismatch, source file is a directory in destination",
+                    EntryKind::Directory => "Mismatch, source directory is a file in destination",
+                };
+                self.log_message(&format!("{}: {}", message, path.display()));
+            }
+            CopyEvent::Notice { message } => self.log_message(message),
+        }
+    }
+}
+
+fn format_time(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+    let secs = duration.as_secs();
+
+    let (hour, remainder) = (secs / 3600, secs % 3600);
+    let (min, sec) = (remainder / 60, remainder % 60);
+
+    format!("{:02}:{:02}:{:02}", hour % 24, min, sec)
+}
+
+fn format_failures(failures: &[String]) -> String {
+    if failures.is_empty() {
+        return String::new();
+    }
+
+    let mut result = String::from("Failures:\n");
+    for failure in failures {
+        result.push_str(&format!("    {}\n", failure));
+    }
+    result.push('\n');
+    result
+}
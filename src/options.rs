@@ -0,0 +1,689 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Most destination entries a purge may delete, set with /MAXDEL
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteLimit {
+    /// A number of files and directories
+    Count(u64),
+    /// A percentage of all files and directories in the destination
+    Percent(u8),
+}
+
+impl fmt::Display for DeleteLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeleteLimit::Count(count) => write!(f, "{}", count),
+            DeleteLimit::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// Hash that /CHECKSUM compares files with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumAlgorithm {
+    /// XXH3, fast but only meant to detect changes
+    #[default]
+    Xxh3,
+    /// SHA-256, slower but cryptographic
+    Sha256,
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChecksumAlgorithm::Xxh3 => write!(f, "XXH3"),
+            ChecksumAlgorithm::Sha256 => write!(f, "SHA256"),
+        }
+    }
+}
+
+/// Settings of a copy job, created through [`CopyOptions::builder`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopyOptions {
+    pub(crate) recursive: bool,
+    pub(crate) include_empty: bool,
+    pub(crate) restartable: bool,
+    pub(crate) backup_mode: bool,
+    pub(crate) purge: bool,
+    pub(crate) mirror: bool,
+    pub(crate) move_files: bool,
+    pub(crate) move_dirs: bool,
+    pub(crate) attributes_add: String,
+    pub(crate) attributes_remove: String,
+    pub(crate) threads: usize,
+    pub(crate) retries: usize,
+    pub(crate) wait_time: u64,
+    pub(crate) retry_backoff: u32,  // Multiplier applied to the wait time after every retry
+    pub(crate) max_wait_time: u64,  // Upper bound for a single wait between retries
+    pub(crate) max_total_wait: u64,  // Upper bound for all waits of one file, 0 means no limit
+    pub(crate) retry_jitter: u64,  // Random variation of each wait, in percent
+    pub(crate) log_file: Option<String>,
+    pub(crate) list_only: bool,
+    pub(crate) show_progress: bool,
+    pub(crate) log_file_names: bool,
+    pub(crate) empty_files: bool,  // New option for creating empty files
+    pub(crate) child_only: bool,  // New option for processing only direct child folders
+    pub(crate) shred_files: bool,  // New option for secure file deletion
+    pub(crate) atomic_writes: bool,  // Write to a temporary file and rename it into place
+    pub(crate) exclude_files: Vec<String>,  // Names or relative paths of files to leave out
+    pub(crate) exclude_dirs: Vec<String>,  // Names or relative paths of directories to leave out
+    pub(crate) exclude_older: bool,  // Leave out files whose source is older than the destination
+    pub(crate) include_older: bool,  // Also copy files whose source is older, overwriting newer destination files
+    pub(crate) exclude_newer: bool,  // Leave out files whose source is newer than the destination
+    pub(crate) exclude_changed: bool,  // Leave out files with the same timestamp but a different size
+    pub(crate) exclude_extra: bool,  // Neither report nor purge destination entries missing from the source
+    pub(crate) exclude_lonely: bool,  // Leave out files and directories that do not exist in the destination yet
+    pub(crate) include_same: bool,  // Also copy files with the same timestamp, size and permissions
+    pub(crate) include_tweaked: bool,  // Also copy files with the same timestamp and size but different permissions
+    pub(crate) use_ignore_files: bool,  // Leave out what .gitignore, .ignore and .colemenignore files list
+    pub(crate) purge_all: bool,  // Purge destination files whether or not they match the file patterns
+    pub(crate) max_delete: Option<DeleteLimit>,  // Abort the job if the purge would delete more than this
+    pub(crate) force: bool,  // Purge even when the source is empty or the destination looks wrong
+    pub(crate) backup_dir: Option<PathBuf>,  // Move deleted and overwritten destination files here
+    pub(crate) backup_versions: usize,  // Number of backup runs to keep, 0 keeps all
+    pub(crate) backup_days: u64,  // Age in days after which backups are deleted, 0 keeps them forever
+    pub(crate) verify_moves: bool,  // Only delete a moved source once the copy has the same hash
+    pub(crate) reflink: bool,  // Let copies share their data with the source on copy-on-write file systems
+    pub(crate) sparsify: bool,  // Turn blocks of zeros into holes in the destination
+    pub(crate) verify: bool,  // Read every copy back and compare it with a hash taken while copying
+    pub(crate) checksum: Option<ChecksumAlgorithm>,  // Compare files of the same size by their contents instead of timestamps
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            recursive: false,
+            include_empty: false,
+            restartable: false,
+            backup_mode: false,
+            purge: false,
+            mirror: false,
+            move_files: false,
+            move_dirs: false,
+            attributes_add: String::new(),
+            attributes_remove: String::new(),
+            threads: 1,
+            retries: 1_000_000,
+            wait_time: 30,
+            retry_backoff: 2,
+            max_wait_time: 600,
+            max_total_wait: 0,
+            retry_jitter: 20,
+            log_file: None,
+            list_only: false,
+            show_progress: true,
+            log_file_names: true,
+            empty_files: false,  // Default to false
+            child_only: false,  // Default to false
+            shred_files: false,  // Default to false
+            atomic_writes: false,
+            exclude_files: Vec::new(),
+            exclude_dirs: Vec::new(),
+            exclude_older: false,
+            include_older: false,
+            exclude_newer: false,
+            exclude_changed: false,
+            exclude_extra: false,
+            exclude_lonely: false,
+            include_same: false,
+            include_tweaked: false,
+            use_ignore_files: false,
+            purge_all: false,
+            max_delete: None,
+            force: false,
+            backup_dir: None,
+            backup_versions: 0,
+            backup_days: 0,
+            verify_moves: false,
+            reflink: false,
+            sparsify: false,
+            verify: false,
+            checksum: None,
+        }
+    }
+}
+
+impl CopyOptions {
+    /// Start building options from the defaults
+    pub fn builder() -> CopyOptionsBuilder {
+        CopyOptionsBuilder::default()
+    }
+
+    /// Check for combinations of options that contradict each other
+    ///
+    /// [`CopyJob::run`](crate::CopyJob::run) refuses to start with invalid options.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |message: String| Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        let move_option = if self.move_dirs { "/MOVE" } else { "/MOV" };
+
+        if self.list_only && self.move_files {
+            return invalid(format!("/L cannot be combined with {}, a listing never changes the source", move_option));
+        }
+
+        if self.verify_moves && !self.move_files {
+            return invalid("/VERIFYMOVE needs /MOV or /MOVE".to_string());
+        }
+
+        if self.empty_files && self.move_files {
+            return invalid(format!(
+                "/EMPTY cannot be combined with {}, the source files would be replaced by empty copies",
+                move_option
+            ));
+        }
+
+        if let Some(DeleteLimit::Percent(percent)) = self.max_delete
+            && percent > 100
+        {
+            return invalid(format!("/MAXDEL:{}% is more than 100 percent", percent));
+        }
+
+        if self.backup_dir.is_none() && (self.backup_versions > 0 || self.backup_days > 0) {
+            return invalid("/BACKUPKEEP and /BACKUPDAYS need a /BACKUPDIR to prune".to_string());
+        }
+
+        if self.include_older && self.exclude_older {
+            return invalid("/IO cannot be combined with /XO".to_string());
+        }
+
+        if self.verify && self.reflink {
+            return invalid("/V cannot be combined with /REFLINK, a reflinked copy shares its data with the source".to_string());
+        }
+
+        if self.backup_dir.is_some() && self.shred_files {
+            return invalid("/SHRED cannot be combined with /BACKUPDIR, backed up files are never shredded".to_string());
+        }
+
+        for attributes in [&self.attributes_add, &self.attributes_remove] {
+            if let Some(c) = attributes.chars().find(|c| !"RASHCNETO".contains(*c)) {
+                return invalid(format!("Unknown file attribute '{}', expected one of RASHCNETO", c));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Builder for [`CopyOptions`]
+#[derive(Debug, Default, Clone)]
+pub struct CopyOptionsBuilder {
+    options: CopyOptions,
+}
+
+impl CopyOptionsBuilder {
+    /// Copy subdirectories (/S)
+    pub fn recursive(&mut self, value: bool) -> &mut Self {
+        self.options.recursive = value;
+        self
+    }
+
+    /// Also copy empty subdirectories (/E)
+    pub fn include_empty(&mut self, value: bool) -> &mut Self {
+        self.options.include_empty = value;
+        self
+    }
+
+    /// Copy in restartable mode, resuming interrupted files (/Z)
+    pub fn restartable(&mut self, value: bool) -> &mut Self {
+        self.options.restartable = value;
+        self
+    }
+
+    /// Copy in backup mode (/B)
+    pub fn backup_mode(&mut self, value: bool) -> &mut Self {
+        self.options.backup_mode = value;
+        self
+    }
+
+    /// Delete destination entries that no longer exist in the source (/PURGE)
+    pub fn purge(&mut self, value: bool) -> &mut Self {
+        self.options.purge = value;
+        self
+    }
+
+    /// Mirror the directory tree (/MIR)
+    pub fn mirror(&mut self, value: bool) -> &mut Self {
+        self.options.mirror = value;
+        self
+    }
+
+    /// Delete source files after copying them (/MOV)
+    pub fn move_files(&mut self, value: bool) -> &mut Self {
+        self.options.move_files = value;
+        self
+    }
+
+    /// Delete source files and directories after copying them (/MOVE)
+    pub fn move_dirs(&mut self, value: bool) -> &mut Self {
+        self.options.move_dirs = value;
+        self
+    }
+
+    /// Only delete the source of a moved file once the copy has the same SHA-256 (/VERIFYMOVE)
+    pub fn verify_moves(&mut self, value: bool) -> &mut Self {
+        self.options.verify_moves = value;
+        self
+    }
+
+    /// Attributes to add to copied files (/A+)
+    pub fn attributes_add(&mut self, value: impl Into<String>) -> &mut Self {
+        self.options.attributes_add = value.into();
+        self
+    }
+
+    /// Attributes to remove from copied files (/A-)
+    pub fn attributes_remove(&mut self, value: impl Into<String>) -> &mut Self {
+        self.options.attributes_remove = value.into();
+        self
+    }
+
+    /// Number of worker threads copying files (/MT)
+    pub fn threads(&mut self, value: usize) -> &mut Self {
+        self.options.threads = value.max(1);
+        self
+    }
+
+    /// Number of retries on failed copies (/R)
+    pub fn retries(&mut self, value: usize) -> &mut Self {
+        self.options.retries = value;
+        self
+    }
+
+    /// Wait time between retries in seconds (/W)
+    pub fn wait_time(&mut self, value: u64) -> &mut Self {
+        self.options.wait_time = value;
+        self
+    }
+
+    /// Multiplier applied to the wait time after every retry (/BACKOFF)
+    pub fn retry_backoff(&mut self, value: u32) -> &mut Self {
+        self.options.retry_backoff = value;
+        self
+    }
+
+    /// Maximum wait between two retries in seconds (/WMAX)
+    pub fn max_wait_time(&mut self, value: u64) -> &mut Self {
+        self.options.max_wait_time = value;
+        self
+    }
+
+    /// Maximum total wait per file in seconds, 0 means no limit (/WTOTAL)
+    pub fn max_total_wait(&mut self, value: u64) -> &mut Self {
+        self.options.max_total_wait = value;
+        self
+    }
+
+    /// Random variation of each wait in percent (/JITTER)
+    pub fn retry_jitter(&mut self, value: u64) -> &mut Self {
+        self.options.retry_jitter = value.min(100);
+        self
+    }
+
+    /// Also write the output to this file (/LOG)
+    pub fn log_file(&mut self, value: impl Into<String>) -> &mut Self {
+        self.options.log_file = Some(value.into());
+        self
+    }
+
+    /// Only list what would be done (/L)
+    pub fn list_only(&mut self, value: bool) -> &mut Self {
+        self.options.list_only = value;
+        self
+    }
+
+    /// Display the progress of each file (disabled by /NP)
+    pub fn show_progress(&mut self, value: bool) -> &mut Self {
+        self.options.show_progress = value;
+        self
+    }
+
+    /// Log the name of every file (disabled by /NFL)
+    pub fn log_file_names(&mut self, value: bool) -> &mut Self {
+        self.options.log_file_names = value;
+        self
+    }
+
+    /// Create empty copies of files (/EMPTY)
+    pub fn empty_files(&mut self, value: bool) -> &mut Self {
+        self.options.empty_files = value;
+        self
+    }
+
+    /// Process only the direct child directories of the source (/CHILDONLY)
+    pub fn child_only(&mut self, value: bool) -> &mut Self {
+        self.options.child_only = value;
+        self
+    }
+
+    /// Securely overwrite files before deleting them (/SHRED)
+    pub fn shred_files(&mut self, value: bool) -> &mut Self {
+        self.options.shred_files = value;
+        self
+    }
+
+    /// Make copies share their data with the source where the file system supports reflinks (/REFLINK)
+    pub fn reflink(&mut self, value: bool) -> &mut Self {
+        self.options.reflink = value;
+        self
+    }
+
+    /// Leave holes in the destination for blocks of zeros, even if the source has none (/SPARSE)
+    pub fn sparsify(&mut self, value: bool) -> &mut Self {
+        self.options.sparsify = value;
+        self
+    }
+
+    /// Read every copy back from the destination and copy it again if it differs (/V)
+    pub fn verify(&mut self, value: bool) -> &mut Self {
+        self.options.verify = value;
+        self
+    }
+
+    /// Decide whether files of the same size differ by hashing them (/CHECKSUM)
+    pub fn checksum(&mut self, value: ChecksumAlgorithm) -> &mut Self {
+        self.options.checksum = Some(value);
+        self
+    }
+
+    /// Write to a temporary file and rename it into place (/ATOMIC)
+    pub fn atomic_writes(&mut self, value: bool) -> &mut Self {
+        self.options.atomic_writes = value;
+        self
+    }
+
+    /// Leave out files whose name, or path relative to the source, matches the pattern (/XF)
+    pub fn exclude_file(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.options.exclude_files.push(pattern.into());
+        self
+    }
+
+    /// Leave out directories whose name, or path relative to the source, matches the pattern (/XD)
+    pub fn exclude_dir(&mut self, pattern: impl Into<String>) -> &mut Self {
+        self.options.exclude_dirs.push(pattern.into());
+        self
+    }
+
+    /// Leave out files that are older in the source than in the destination (/XO)
+    pub fn exclude_older(&mut self, value: bool) -> &mut Self {
+        self.options.exclude_older = value;
+        self
+    }
+
+    /// Also copy files that are older in the source, overwriting newer destination files (/IO)
+    pub fn include_older(&mut self, value: bool) -> &mut Self {
+        self.options.include_older = value;
+        self
+    }
+
+    /// Leave out files that are newer in the source than in the destination (/XN)
+    pub fn exclude_newer(&mut self, value: bool) -> &mut Self {
+        self.options.exclude_newer = value;
+        self
+    }
+
+    /// Leave out files with the same timestamp but a different size (/XC)
+    pub fn exclude_changed(&mut self, value: bool) -> &mut Self {
+        self.options.exclude_changed = value;
+        self
+    }
+
+    /// Leave destination entries that are missing from the source alone, even when purging (/XX)
+    pub fn exclude_extra(&mut self, value: bool) -> &mut Self {
+        self.options.exclude_extra = value;
+        self
+    }
+
+    /// Leave out files and directories the destination does not have yet (/XL)
+    pub fn exclude_lonely(&mut self, value: bool) -> &mut Self {
+        self.options.exclude_lonely = value;
+        self
+    }
+
+    /// Copy files that are the same in source and destination as well (/IS)
+    pub fn include_same(&mut self, value: bool) -> &mut Self {
+        self.options.include_same = value;
+        self
+    }
+
+    /// Copy files that only differ in their permissions as well (/IT)
+    pub fn include_tweaked(&mut self, value: bool) -> &mut Self {
+        self.options.include_tweaked = value;
+        self
+    }
+
+    /// Purge destination files that do not match the file patterns too (/PURGEALL)
+    pub fn purge_all(&mut self, value: bool) -> &mut Self {
+        self.options.purge_all = value;
+        self
+    }
+
+    /// Refuse to purge if more destination entries than this would be deleted (/MAXDEL)
+    pub fn max_delete(&mut self, value: DeleteLimit) -> &mut Self {
+        self.options.max_delete = Some(value);
+        self
+    }
+
+    /// Purge even an empty source onto the root, the home or an unmarked directory (/FORCE)
+    pub fn force(&mut self, value: bool) -> &mut Self {
+        self.options.force = value;
+        self
+    }
+
+    /// Move destination files into a timestamped tree here instead of deleting or overwriting them (/BACKUPDIR)
+    pub fn backup_dir(&mut self, value: impl Into<PathBuf>) -> &mut Self {
+        self.options.backup_dir = Some(value.into());
+        self
+    }
+
+    /// Only keep the backups of this many runs, 0 keeps all (/BACKUPKEEP)
+    pub fn backup_versions(&mut self, value: usize) -> &mut Self {
+        self.options.backup_versions = value;
+        self
+    }
+
+    /// Delete backups older than this many days, 0 keeps them forever (/BACKUPDAYS)
+    pub fn backup_days(&mut self, value: u64) -> &mut Self {
+        self.options.backup_days = value;
+        self
+    }
+
+    /// Leave out what `.gitignore`, `.ignore` and `.colemenignore` files list (/IGNOREFILES)
+    pub fn use_ignore_files(&mut self, value: bool) -> &mut Self {
+        self.options.use_ignore_files = value;
+        self
+    }
+
+    /// Finish building the options
+    pub fn build(&self) -> CopyOptions {
+        self.options.clone()
+    }
+}
+
+pub(crate) fn format_options(options: &CopyOptions) -> String {
+    let mut result = Vec::new();
+
+    if options.recursive {
+        if options.include_empty {
+            result.push("/E".to_string());
+        } else {
+            result.push("/S".to_string());
+        }
+    }
+
+    if options.restartable {
+        result.push("/Z".to_string());
+    }
+
+    if options.backup_mode {
+        result.push("/B".to_string());
+    }
+
+    if options.mirror {
+        result.push("/MIR".to_string());
+    } else if options.purge {
+        result.push("/PURGE".to_string());
+    }
+
+    if options.purge_all {
+        result.push("/PURGEALL".to_string());
+    }
+
+    if let Some(limit) = options.max_delete {
+        result.push(format!("/MAXDEL:{}", limit));
+    }
+
+    if options.force {
+        result.push("/FORCE".to_string());
+    }
+
+    if let Some(backup_dir) = &options.backup_dir {
+        result.push(format!("/BACKUPDIR:{}", backup_dir.display()));
+    }
+
+    if options.backup_versions > 0 {
+        result.push(format!("/BACKUPKEEP:{}", options.backup_versions));
+    }
+
+    if options.backup_days > 0 {
+        result.push(format!("/BACKUPDAYS:{}", options.backup_days));
+    }
+
+    if options.move_dirs {
+        result.push("/MOVE".to_string());
+    } else if options.move_files {
+        result.push("/MOV".to_string());
+    }
+
+    if options.verify_moves {
+        result.push("/VERIFYMOVE".to_string());
+    }
+
+    if !options.attributes_add.is_empty() {
+        result.push(format!("/A+:{}", options.attributes_add));
+    }
+
+    if !options.attributes_remove.is_empty() {
+        result.push(format!("/A-:{}", options.attributes_remove));
+    }
+
+    if options.threads > 1 {
+        result.push(format!("/MT:{}", options.threads));
+    }
+
+    if options.retries != 1_000_000 {
+        result.push(format!("/R:{}", options.retries));
+    }
+
+    if options.wait_time != 30 {
+        result.push(format!("/W:{}", options.wait_time));
+    }
+
+    if options.retry_backoff != 2 {
+        result.push(format!("/BACKOFF:{}", options.retry_backoff));
+    }
+
+    if options.max_wait_time != 600 {
+        result.push(format!("/WMAX:{}", options.max_wait_time));
+    }
+
+    if options.max_total_wait != 0 {
+        result.push(format!("/WTOTAL:{}", options.max_total_wait));
+    }
+
+    if options.retry_jitter != 20 {
+        result.push(format!("/JITTER:{}", options.retry_jitter));
+    }
+
+    if options.list_only {
+        result.push("/L".to_string());
+    }
+
+    if !options.show_progress {
+        result.push("/NP".to_string());
+    }
+
+    if !options.log_file_names {
+        result.push("/NFL".to_string());
+    }
+
+    if options.empty_files {
+        result.push("/EMPTY".to_string());
+    }
+
+    if options.child_only {
+        result.push("/CHILDONLY".to_string());
+    }
+
+    if options.shred_files {
+        result.push("/SHRED".to_string());
+    }
+
+    if options.atomic_writes {
+        result.push("/ATOMIC".to_string());
+    }
+
+    if options.reflink {
+        result.push("/REFLINK".to_string());
+    }
+
+    if options.sparsify {
+        result.push("/SPARSE".to_string());
+    }
+
+    if options.verify {
+        result.push("/V".to_string());
+    }
+
+    match options.checksum {
+        Some(ChecksumAlgorithm::Xxh3) => result.push("/CHECKSUM".to_string()),
+        Some(algorithm) => result.push(format!("/CHECKSUM:{}", algorithm)),
+        None => {}
+    }
+
+    if !options.exclude_files.is_empty() {
+        result.push(format!("/XF {}", options.exclude_files.join(" ")));
+    }
+
+    if !options.exclude_dirs.is_empty() {
+        result.push(format!("/XD {}", options.exclude_dirs.join(" ")));
+    }
+
+    if options.exclude_older {
+        result.push("/XO".to_string());
+    }
+
+    if options.include_older {
+        result.push("/IO".to_string());
+    }
+
+    if options.exclude_newer {
+        result.push("/XN".to_string());
+    }
+
+    if options.exclude_changed {
+        result.push("/XC".to_string());
+    }
+
+    if options.exclude_extra {
+        result.push("/XX".to_string());
+    }
+
+    if options.exclude_lonely {
+        result.push("/XL".to_string());
+    }
+
+    if options.include_same {
+        result.push("/IS".to_string());
+    }
+
+    if options.include_tweaked {
+        result.push("/IT".to_string());
+    }
+
+    if options.use_ignore_files {
+        result.push("/IGNOREFILES".to_string());
+    }
+
+    result.join(" ")
+}
@@ -0,0 +1,516 @@
+use std::borrow::Cow;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::ignore::IgnoreRules;
+use crate::options::CopyOptions;
+
+/// One element of a compiled glob
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(char),
+    /// `?`, any single character except `/`
+    AnyChar,
+    /// `*`, any run of characters except `/`
+    AnySequence,
+    /// `**`, any run of characters including `/`
+    AnyPath,
+    /// `**/`, nothing or any run of whole directories
+    AnyDirectories,
+    /// `[...]`, one character out of the ranges, or not in them if negated
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+/// A shell style wildcard pattern
+///
+/// Supports `*`, `?`, character classes like `[0-9]` or `[!a-z]`, brace alternation like
+/// `{jpg,png}` and `**` to match across directories. Characters that are otherwise special can
+/// be matched literally through a class, e.g. `[*]`.
+#[derive(Debug, Clone)]
+pub(crate) struct Glob {
+    // Braces are expanded when compiling, so each alternative is a plain token list
+    alternatives: Vec<Vec<Token>>,
+}
+
+impl Glob {
+    pub(crate) fn new(pattern: &str) -> io::Result<Self> {
+        let invalid = |message: &str| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid pattern {}: {}", pattern, message))
+        };
+
+        let alternatives = expand_braces(pattern).map_err(invalid)?;
+        let alternatives = alternatives
+            .iter()
+            .map(|alternative| compile(alternative))
+            .collect::<Result<_, _>>()
+            .map_err(invalid)?;
+
+        Ok(Glob { alternatives })
+    }
+
+    pub(crate) fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.alternatives.iter().any(|tokens| matches_tokens(tokens, &text))
+    }
+}
+
+/// Expand `{a,b}` alternatives, including nested ones, into plain patterns
+fn expand_braces(pattern: &str) -> Result<Vec<String>, &'static str> {
+    let chars: Vec<char> = pattern.chars().collect();
+
+    // Find the first top level brace group; braces inside a class are literal
+    let mut open = None;
+    let mut in_class = false;
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            '{' if !in_class => {
+                if depth == 0 {
+                    open = Some(i);
+                }
+                depth += 1;
+            }
+            ',' if !in_class && depth == 1 => commas.push(i),
+            '}' if !in_class && depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let open = open.unwrap();
+                    let prefix: String = chars[..open].iter().collect();
+                    let suffix: String = chars[i + 1..].iter().collect();
+
+                    let mut bounds = vec![open];
+                    bounds.extend(&commas);
+                    bounds.push(i);
+
+                    let mut result = Vec::new();
+                    for window in bounds.windows(2) {
+                        let alternative: String = chars[window[0] + 1..window[1]].iter().collect();
+                        // The alternative may contain more braces, and so may the rest of the pattern
+                        for expanded in expand_braces(&format!("{}{}{}", prefix, alternative, suffix))? {
+                            result.push(expanded);
+                        }
+                    }
+                    return Ok(result);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if depth > 0 {
+        return Err("unclosed '{'");
+    }
+    Ok(vec![pattern.to_string()])
+}
+
+fn compile(pattern: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '?' => tokens.push(Token::AnyChar),
+            '*' => {
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        tokens.push(Token::AnyDirectories);
+                    } else {
+                        tokens.push(Token::AnyPath);
+                    }
+                } else {
+                    tokens.push(Token::AnySequence);
+                }
+            }
+            '[' => {
+                let negated = matches!(chars.peek(), Some('!' | '^'));
+                if negated {
+                    chars.next();
+                }
+
+                let mut ranges = Vec::new();
+                let mut first = true;
+                loop {
+                    let Some(c) = chars.next() else {
+                        return Err("unclosed '['");
+                    };
+                    // A ']' right after the opening bracket is part of the class
+                    if c == ']' && !first {
+                        break;
+                    }
+                    first = false;
+
+                    let mut lookahead = chars.clone();
+                    if lookahead.next() == Some('-')
+                        && let Some(end) = lookahead.next()
+                        && end != ']'
+                    {
+                        chars.next();
+                        chars.next();
+                        ranges.push((c, end));
+                    } else {
+                        ranges.push((c, c));
+                    }
+                }
+                tokens.push(Token::Class { negated, ranges });
+            }
+            c => tokens.push(Token::Literal(c)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Whether `tokens` match all of `text`
+///
+/// Works backwards through the tokens, keeping for every position in the text whether the rest
+/// of the pattern matches from there. That takes time proportional to the pattern length times
+/// the text length, where backtracking over several `*` would take exponential time.
+fn matches_tokens(tokens: &[Token], text: &[char]) -> bool {
+    // `next[j]` is whether the tokens after the current one match `text[j..]`
+    let mut next = vec![false; text.len() + 1];
+    next[text.len()] = true;
+
+    for token in tokens.iter().rev() {
+        let mut current = vec![false; text.len() + 1];
+        // Whether the tokens after `**/` match right after some `/` at or beyond the position
+        let mut after_slash = false;
+
+        for j in (0..=text.len()).rev() {
+            let c = text.get(j).copied();
+            current[j] = match token {
+                Token::Literal(literal) => c == Some(*literal) && next[j + 1],
+                Token::AnyChar => c.is_some_and(|c| c != '/') && next[j + 1],
+                Token::Class { negated, ranges } => {
+                    c.is_some_and(|c| {
+                        c != '/' && ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+                    }) && next[j + 1]
+                }
+                Token::AnySequence => next[j] || (c.is_some_and(|c| c != '/') && current[j + 1]),
+                Token::AnyPath => next[j] || (c.is_some() && current[j + 1]),
+                Token::AnyDirectories => {
+                    after_slash |= c == Some('/') && next[j + 1];
+                    next[j] || after_slash
+                }
+            };
+        }
+        next = current;
+    }
+
+    next[0]
+}
+
+/// An exclusion pattern, matched against a bare name or, if it contains a `/`, against the path
+/// relative to the source root
+#[derive(Debug, Clone)]
+struct Exclusion {
+    glob: Glob,
+    relative: bool,
+}
+
+impl Exclusion {
+    fn new(pattern: &str) -> io::Result<Self> {
+        // A leading slash anchors a single name at the source root, like `/build`
+        let relative = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        Ok(Exclusion { glob: Glob::new(pattern)?, relative })
+    }
+
+    fn matches(&self, name: &str, relative_path: &str) -> bool {
+        if self.relative {
+            self.glob.matches(relative_path)
+        } else {
+            self.glob.matches(name)
+        }
+    }
+}
+
+/// Decides which files and directories of the source take part in the copy
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Filter {
+    root: PathBuf,
+    include: Vec<Glob>,
+    exclude_files: Vec<Exclusion>,
+    exclude_dirs: Vec<Exclusion>,
+    use_ignore_files: bool,
+    purge_all: bool,
+    // Without /S or /E no directory is copied, so none can be purged either
+    recursive: bool,
+    // Rules of the ignore files found from the source root down to the current directory
+    ignore_rules: Vec<Arc<IgnoreRules>>,
+}
+
+impl Filter {
+    /// Compile the include patterns of a job and the exclusions of its options
+    pub(crate) fn new(root: &Path, include: &[String], options: &CopyOptions) -> io::Result<Self> {
+        let compile = |patterns: &[String]| {
+            patterns.iter().map(|pattern| Exclusion::new(pattern)).collect::<io::Result<Vec<_>>>()
+        };
+
+        // Like in Robocopy, *.* also matches names without an extension, so it includes everything
+        let include = if include.iter().any(|pattern| pattern == "*.*") {
+            Vec::new()
+        } else {
+            include.iter().map(|pattern| Glob::new(pattern)).collect::<io::Result<_>>()?
+        };
+
+        Ok(Filter {
+            root: root.to_path_buf(),
+            include,
+            exclude_files: compile(&options.exclude_files)?,
+            exclude_dirs: compile(&options.exclude_dirs)?,
+            use_ignore_files: options.use_ignore_files,
+            purge_all: options.purge_all,
+            recursive: options.recursive,
+            ignore_rules: Vec::new(),
+        })
+    }
+
+    /// The filter for the contents of `dir`, which adds the rules of its ignore files
+    pub(crate) fn enter(&self, dir: &Path) -> io::Result<Cow<'_, Filter>> {
+        if !self.use_ignore_files {
+            return Ok(Cow::Borrowed(self));
+        }
+
+        match IgnoreRules::load(dir)? {
+            Some(rules) => {
+                let mut filter = self.clone();
+                filter.ignore_rules.push(Arc::new(rules));
+                Ok(Cow::Owned(filter))
+            }
+            None => Ok(Cow::Borrowed(self)),
+        }
+    }
+
+    /// Whether purging may delete the destination entry that corresponds to `path` in the source
+    ///
+    /// Only entries the copy could have created are candidates: files matching the include
+    /// patterns (any file with /PURGEALL), directories when copying subdirectories, and nothing
+    /// excluded or ignored.
+    pub(crate) fn in_purge_scope(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dir {
+            self.recursive && !self.excludes_dir(path) && !self.ignores(path, true)
+        } else {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            (self.purge_all || self.includes_file(&name)) && !self.excludes_file(path) && !self.ignores(path, false)
+        }
+    }
+
+    /// Whether every entry is in purge scope, whatever its name or place
+    pub(crate) fn purges_everything(&self) -> bool {
+        (self.purge_all || self.include.is_empty())
+            && self.exclude_files.is_empty()
+            && self.exclude_dirs.is_empty()
+            && !self.use_ignore_files
+    }
+
+    /// Whether every file and directory is copied, whatever its name or place
+    pub(crate) fn takes_everything(&self) -> bool {
+        self.include.is_empty()
+            && self.exclude_files.is_empty()
+            && self.exclude_dirs.is_empty()
+            && !self.use_ignore_files
+    }
+
+    /// Whether an ignore file rules out the entry at `path` in the source tree
+    pub(crate) fn ignores(&self, path: &Path, is_dir: bool) -> bool {
+        // Deeper ignore files override the ones above them
+        self.ignore_rules
+            .iter()
+            .rev()
+            .find_map(|rules| rules.decide(path, is_dir))
+            .unwrap_or(false)
+    }
+
+    /// Whether a file with this name is copied; without patterns every file is
+    pub(crate) fn includes_file(&self, file_name: &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|glob| glob.matches(file_name))
+    }
+
+    /// Whether the file at `path` in the source tree is excluded by /XF
+    pub(crate) fn excludes_file(&self, path: &Path) -> bool {
+        self.excluded(&self.exclude_files, path)
+    }
+
+    /// Whether the directory at `path` in the source tree is excluded by /XD
+    pub(crate) fn excludes_dir(&self, path: &Path) -> bool {
+        self.excluded(&self.exclude_dirs, path)
+    }
+
+    fn excluded(&self, exclusions: &[Exclusion], path: &Path) -> bool {
+        if exclusions.is_empty() {
+            return false;
+        }
+
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let relative_path = relative_path(&self.root, path);
+        exclusions.iter().any(|exclusion| exclusion.matches(&name, &relative_path))
+    }
+}
+
+/// `path` relative to `root`, with `/` as separator on every platform
+pub(crate) fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let components: Vec<_> = relative.iter().map(|component| component.to_string_lossy()).collect();
+    components.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        Glob::new(pattern).unwrap()
+    }
+
+    fn filter(patterns: &[&str], options: &CopyOptions) -> Filter {
+        let patterns: Vec<String> = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        Filter::new(Path::new("/src"), &patterns, options).unwrap()
+    }
+
+    fn in_scope(filter: &Filter, path: &str) -> bool {
+        filter.in_purge_scope(Path::new(path), path.ends_with('/'))
+    }
+
+    #[test]
+    fn star_matches_any_run_within_a_name() {
+        let glob = glob("IMG_*.jpg");
+        assert!(glob.matches("IMG_0001.jpg"));
+        assert!(glob.matches("IMG_.jpg"));
+        assert!(!glob.matches("IMG_0001.png"));
+        assert!(!glob.matches("DSC_0001.jpg"));
+        assert!(!glob.matches("IMG_2024/0001.jpg"));
+    }
+
+    #[test]
+    fn star_matches_before_a_double_extension() {
+        let glob = glob("*.tar.gz");
+        assert!(glob.matches("backup.tar.gz"));
+        assert!(glob.matches("backup.2024.tar.gz"));
+        assert!(!glob.matches("backup.tar"));
+        assert!(!glob.matches("backup.gz"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        let glob = glob("file?.txt");
+        assert!(glob.matches("file1.txt"));
+        assert!(!glob.matches("file.txt"));
+        assert!(!glob.matches("file12.txt"));
+        assert!(!glob.matches("file/.txt"));
+    }
+
+    #[test]
+    fn classes_match_ranges_and_their_negation() {
+        let digits = glob("log[0-9].txt");
+        assert!(digits.matches("log7.txt"));
+        assert!(!digits.matches("logx.txt"));
+
+        let not_digits = glob("log[!0-9].txt");
+        assert!(not_digits.matches("logx.txt"));
+        assert!(!not_digits.matches("log7.txt"));
+
+        // Special characters are literal inside a class, and so is a leading ']'
+        assert!(glob("[*]").matches("*"));
+        assert!(!glob("[*]").matches("a"));
+        assert!(glob("[]]").matches("]"));
+    }
+
+    #[test]
+    fn braces_match_either_alternative() {
+        let glob = glob("*.{jpg,png}");
+        assert!(glob.matches("photo.jpg"));
+        assert!(glob.matches("photo.png"));
+        assert!(!glob.matches("photo.gif"));
+    }
+
+    #[test]
+    fn braces_expand_nested_and_repeated_groups() {
+        assert_eq!(expand_braces("a{b,c}d").unwrap(), ["abd", "acd"]);
+        assert_eq!(expand_braces("{a,b{c,d}}").unwrap(), ["a", "bc", "bd"]);
+        assert_eq!(expand_braces("{a,b}{1,2}").unwrap(), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand_braces("[{]").unwrap(), ["[{]"]);
+        assert_eq!(expand_braces("{a,b"), Err("unclosed '{'"));
+    }
+
+    #[test]
+    fn double_star_matches_across_directories() {
+        let any_path = glob("src/**.rs");
+        assert!(any_path.matches("src/main.rs"));
+        assert!(any_path.matches("src/a/b/main.rs"));
+
+        let any_dirs = glob("**/target");
+        assert!(any_dirs.matches("target"));
+        assert!(any_dirs.matches("a/b/target"));
+        assert!(!any_dirs.matches("a/mytarget"));
+    }
+
+    #[test]
+    fn compile_rejects_an_unclosed_class() {
+        assert_eq!(compile("file[0-9").unwrap_err(), "unclosed '['");
+        assert!(Glob::new("file[0-9").is_err());
+    }
+
+    #[test]
+    fn many_stars_do_not_backtrack_exponentially() {
+        let name = "a".repeat(200);
+        assert!(!glob("*a*a*a*a*a*a*a*a*a*a*b").matches(&name));
+        assert!(glob("*a*a*a*a*a*a*a*a*a*a").matches(&name));
+        assert!(!glob("**a**a**a**a**a**a**a**a**b").matches(&format!("{}/{}", name, name)));
+        assert!(!glob("**/a*a*a*a*a*a*a*a*b").matches(&vec![name.as_str(); 20].join("/")));
+    }
+
+    #[test]
+    fn tokens_match_the_whole_text() {
+        let tokens = compile("a*").unwrap();
+        assert!(matches_tokens(&tokens, &['a']));
+        assert!(matches_tokens(&tokens, &['a', 'b', 'c']));
+        assert!(!matches_tokens(&tokens, &['b', 'a']));
+        assert!(!matches_tokens(&tokens, &['a', '/', 'b']));
+    }
+
+    #[test]
+    fn purge_scope_follows_the_file_patterns() {
+        let jpg = filter(&["*.jpg"], &CopyOptions::builder().purge(true).build());
+        assert!(in_scope(&jpg, "/src/old.jpg"));
+        assert!(!in_scope(&jpg, "/src/notes.txt"));
+
+        let all = filter(&["*.jpg"], &CopyOptions::builder().purge(true).purge_all(true).build());
+        assert!(in_scope(&all, "/src/old.jpg"));
+        assert!(in_scope(&all, "/src/notes.txt"));
+    }
+
+    #[test]
+    fn purge_scope_leaves_out_exclusions() {
+        let options = CopyOptions::builder()
+            .purge(true)
+            .recursive(true)
+            .exclude_file("*.log")
+            .exclude_dir("cache")
+            .exclude_dir("keep/this")
+            .build();
+        let filter = filter(&[], &options);
+
+        assert!(!in_scope(&filter, "/src/debug.log"));
+        assert!(!in_scope(&filter, "/src/cache/"));
+        assert!(!in_scope(&filter, "/src/keep/this/"));
+        assert!(in_scope(&filter, "/src/this/"));
+        assert!(in_scope(&filter, "/src/old.txt"));
+        assert!(!filter.purges_everything());
+    }
+
+    #[test]
+    fn purge_scope_has_no_directories_without_recursion() {
+        let flat = filter(&[], &CopyOptions::builder().purge(true).build());
+        assert!(!in_scope(&flat, "/src/sub/"));
+        assert!(in_scope(&flat, "/src/old.txt"));
+
+        let recursive = filter(&[], &CopyOptions::builder().purge(true).recursive(true).build());
+        assert!(in_scope(&recursive, "/src/sub/"));
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::backup::Backup;
+use crate::copy::partial_target;
+use crate::engine::{count_tree, purge_directory, JobContext};
+use crate::observer::ObserverList;
+use crate::options::{CopyOptions, DeleteLimit};
+use crate::pattern::Filter;
+use crate::stats::Statistics;
+
+/// File that marks a directory as a colemen_copy destination, which purging may empty
+pub(crate) const DESTINATION_MARKER: &str = ".colemen_destination";
+
+fn refuse(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, message)
+}
+
+/// Refuse purges that look like a mistyped path, unless forced
+///
+/// Catches an empty source, a destination that is the root or the home directory, and a
+/// destination with contents that colemen_copy never mirrored to.
+pub(crate) fn check_purge(source_path: &Path, dest_path: &Path, options: &CopyOptions) -> io::Result<()> {
+    if options.force {
+        return Ok(());
+    }
+
+    if source_path.is_dir() && fs::read_dir(source_path)?.next().is_none() {
+        return Err(refuse(format!(
+            "Refusing to purge {}, the source {} is empty (use /FORCE to purge anyway)",
+            dest_path.display(),
+            source_path.display()
+        )));
+    }
+
+    if !dest_path.exists() {
+        return Ok(());
+    }
+
+    let dest = dest_path.canonicalize()?;
+    if dest.parent().is_none() {
+        return Err(refuse(format!(
+            "Refusing to purge the root directory {} (use /FORCE to purge anyway)",
+            dest_path.display()
+        )));
+    }
+
+    if let Some(home) = env::home_dir()
+        && home.canonicalize().is_ok_and(|home| home == dest)
+    {
+        return Err(refuse(format!(
+            "Refusing to purge the home directory {} (use /FORCE to purge anyway)",
+            dest_path.display()
+        )));
+    }
+
+    if !dest.join(DESTINATION_MARKER).exists() && fs::read_dir(&dest)?.next().is_some() {
+        return Err(refuse(format!(
+            "Refusing to purge {}, it is not empty and was never a colemen_copy destination \
+             (use /FORCE to purge it anyway, later runs will not ask again)",
+            dest_path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Mark `dest_path` as a destination that purging may empty
+pub(crate) fn mark_destination(dest_path: &Path) -> io::Result<()> {
+    let marker = dest_path.join(DESTINATION_MARKER);
+    if marker.exists() {
+        return Ok(());
+    }
+    fs::write(marker, "This directory is mirrored by colemen_copy, entries missing from the source get deleted.\n")
+}
+
+/// Count what the purge would delete, and refuse if it is more than /MAXDEL allows
+pub(crate) fn check_delete_limit(
+    source_path: &Path,
+    dest_path: &Path,
+    filter: &Filter,
+    options: &CopyOptions,
+    context: &JobContext,
+) -> io::Result<()> {
+    let Some(limit) = options.max_delete else {
+        return Ok(());
+    };
+    if !dest_path.exists() {
+        return Ok(());
+    }
+
+    // Purging in list-only mode deletes nothing, it only counts
+    let mut dry_run = options.clone();
+    dry_run.list_only = true;
+    let stats = Mutex::new(Statistics::default());
+    if options.child_only && source_path.is_dir() {
+        let filter = filter.enter(source_path)?;
+        for entry in fs::read_dir(source_path)? {
+            let child_path = entry?.path();
+            if child_path.is_dir() && !filter.excludes_dir(&child_path) && !filter.ignores(&child_path, true) {
+                let child_dest = dest_path.join(child_path.file_name().unwrap_or_default());
+                count_purge(&child_path, &child_dest, &filter, &dry_run, &stats, context.backup);
+            }
+        }
+    } else {
+        count_purge(source_path, dest_path, filter, &dry_run, &stats, context.backup);
+    }
+    let stats = stats.into_inner().unwrap();
+
+    let deleted = (stats.files_removed + stats.dirs_removed) as u64;
+    let (files, dirs) = count_tree(dest_path, Some(context))?;
+    let total = (files + dirs) as u64;
+    let exceeded = match limit {
+        DeleteLimit::Count(count) => deleted > count,
+        DeleteLimit::Percent(percent) => deleted * 100 > total * u64::from(percent),
+    };
+
+    if exceeded {
+        return Err(refuse(format!(
+            "Refusing to purge {}, {} of its {} entries would be deleted, more than /MAXDEL:{} allows \
+             (run with /L to list them)",
+            dest_path.display(),
+            deleted,
+            total,
+            limit
+        )));
+    }
+
+    Ok(())
+}
+
+/// Count into `stats` what purging `dst_dir` would delete, walking both trees like the copy does
+/// but without looking at the files that get copied
+fn count_purge(
+    src_dir: &Path,
+    dst_dir: &Path,
+    filter: &Filter,
+    options: &CopyOptions,
+    stats: &Mutex<Statistics>,
+    backup: Option<&Backup>,
+) {
+    // What the copy cannot read it does not purge either
+    let Ok(filter) = filter.enter(src_dir) else {
+        return;
+    };
+    let Ok(entries) = fs::read_dir(src_dir) else {
+        return;
+    };
+
+    let mut src_entries = HashSet::new();
+    let mut listing_complete = true;
+    for entry in entries {
+        let Ok(entry) = entry else {
+            listing_complete = false;
+            continue;
+        };
+        let path = entry.path();
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+
+        if path.is_file() {
+            if filter.includes_file(&file_name) && !filter.excludes_file(&path) && !filter.ignores(&path, false) {
+                src_entries.insert(file_name);
+            }
+        } else if path.is_dir() && options.recursive && !filter.excludes_dir(&path) && !filter.ignores(&path, true) {
+            // Empty source directories are skipped without /E, and so is what lies below them
+            let dst_subdir = dst_dir.join(&file_name);
+            let skipped = !options.include_empty && path.read_dir().is_ok_and(|mut entries| entries.next().is_none());
+            if dst_subdir.is_dir() && !skipped {
+                count_purge(&path, &dst_subdir, &filter, options, stats, backup);
+            }
+            src_entries.insert(file_name);
+        }
+    }
+
+    if !listing_complete {
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(dst_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        if file_name == DESTINATION_MARKER
+            || backup.is_some_and(|backup| backup.is_backup_dir(&path))
+            || src_entries.contains(&file_name)
+            || partial_target(&file_name).is_some_and(|target| src_entries.contains(target))
+        {
+            continue;
+        }
+
+        let src_path = src_dir.join(partial_target(&file_name).unwrap_or(&file_name));
+        if !filter.in_purge_scope(&src_path, path.is_dir()) {
+            continue;
+        }
+
+        if path.is_file() {
+            stats.lock().unwrap().files_removed += 1;
+        } else if path.is_dir()
+            && purge_directory(&path, &src_path, &filter, options, &ObserverList(&[]), stats, None).unwrap_or(false)
+        {
+            stats.lock().unwrap().dirs_removed += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cancel::CancellationToken;
+    use crate::options::CopyOptionsBuilder;
+    use crate::test_dir::TestDir;
+
+    /// Options of `/MIR`
+    fn mirror() -> CopyOptionsBuilder {
+        let mut options = CopyOptions::builder();
+        options.purge(true).recursive(true).include_empty(true);
+        options
+    }
+
+    fn delete_limit(dir: &TestDir, options: &CopyOptions) -> io::Result<()> {
+        let filter = Filter::new(&dir.join("src"), &[], options).unwrap();
+        let cancel = CancellationToken::new();
+        let context = JobContext { cancel: &cancel, backup: None };
+        check_delete_limit(&dir.join("src"), &dir.join("dst"), &filter, options, &context)
+    }
+
+    /// A marked destination of 9 entries, 4 of them missing from the source: `gone.txt`, and
+    /// `old/` with the two entries nested in it
+    fn tree_with_extras() -> TestDir {
+        let dir = TestDir::new();
+        dir.create(&["src/a.txt", "src/b.txt", "src/sub/c.txt", "src/sub/d.txt"]);
+        dir.create(&["dst/a.txt", "dst/b.txt", "dst/sub/c.txt", "dst/sub/d.txt"]);
+        dir.create(&["dst/gone.txt", "dst/old/e.txt", "dst/old/deeper/"]);
+        mark_destination(&dir.join("dst")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn purging_from_an_empty_source_is_refused() {
+        let dir = TestDir::new();
+        dir.create(&["src/", "dst/photo.jpg"]);
+        mark_destination(&dir.join("dst")).unwrap();
+
+        let error = check_purge(&dir.join("src"), &dir.join("dst"), &mirror().build()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(error.to_string().contains("is empty"), "{}", error);
+    }
+
+    #[test]
+    fn purging_an_unmarked_destination_is_refused() {
+        let dir = TestDir::new();
+        dir.create(&["src/photo.jpg", "dst/thesis.doc"]);
+
+        let error = check_purge(&dir.join("src"), &dir.join("dst"), &mirror().build()).unwrap_err();
+        assert!(error.to_string().contains("never a colemen_copy destination"), "{}", error);
+
+        mark_destination(&dir.join("dst")).unwrap();
+        check_purge(&dir.join("src"), &dir.join("dst"), &mirror().build()).unwrap();
+    }
+
+    #[test]
+    fn purging_a_new_or_empty_destination_is_allowed() {
+        let dir = TestDir::new();
+        dir.create(&["src/photo.jpg", "empty/"]);
+
+        check_purge(&dir.join("src"), &dir.join("missing"), &mirror().build()).unwrap();
+        check_purge(&dir.join("src"), &dir.join("empty"), &mirror().build()).unwrap();
+    }
+
+    #[test]
+    fn force_overrides_the_purge_checks() {
+        let dir = TestDir::new();
+        dir.create(&["empty_src/", "src/photo.jpg", "dst/thesis.doc"]);
+        let forced = mirror().force(true).build();
+
+        check_purge(&dir.join("empty_src"), &dir.join("dst"), &forced).unwrap();
+        check_purge(&dir.join("src"), &dir.join("dst"), &forced).unwrap();
+    }
+
+    #[test]
+    fn delete_limit_counts_entries_nested_in_purged_directories() {
+        let dir = tree_with_extras();
+
+        delete_limit(&dir, &mirror().max_delete(DeleteLimit::Count(4)).build()).unwrap();
+        let error = delete_limit(&dir, &mirror().max_delete(DeleteLimit::Count(3)).build()).unwrap_err();
+        assert!(error.to_string().contains("4 of its 9 entries"), "{}", error);
+    }
+
+    #[test]
+    fn delete_limit_as_a_percentage_leaves_out_the_marker() {
+        let dir = tree_with_extras();
+
+        delete_limit(&dir, &mirror().max_delete(DeleteLimit::Percent(45)).build()).unwrap();
+        let error = delete_limit(&dir, &mirror().max_delete(DeleteLimit::Percent(44)).build()).unwrap_err();
+        assert!(error.to_string().contains("4 of its 9 entries"), "{}", error);
+    }
+
+    #[test]
+    fn delete_limit_does_not_count_what_the_filter_keeps() {
+        let dir = tree_with_extras();
+        let options = mirror().max_delete(DeleteLimit::Count(1)).exclude_dir("old").build();
+
+        delete_limit(&dir, &options).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+use std::fs;
+use std::io::{self, Seek, Write};
+use std::path::Path;
+
+use rand::{thread_rng, Rng};
+
+use crate::observer::{CopyEvent, CopyObserver};
+
+pub(crate) fn securely_delete_file(path: &Path, observer: &dyn CopyObserver) -> io::Result<()> {
+    // Get the file size
+    let metadata = fs::metadata(path)?;
+    let file_size = metadata.len();
+
+    // Open the file for writing
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .open(path)?;
+
+    // Buffer for overwriting
+    const BUFFER_SIZE: usize = 64 * 1024; // 64 KB
+
+    // Multiple overwrite passes with different patterns
+    let patterns = [
+        0xFF, // All ones
+        0x00, // All zeros
+        0xAA, // 10101010
+        0x55, // 01010101
+        0xF0, // 11110000
+        0x0F, // 00001111
+    ];
+
+    let mut buffer = vec![0; BUFFER_SIZE];
+
+    for &pattern in &patterns {
+        // Fill buffer with the current pattern
+        buffer.fill(pattern);
+
+        // Seek to beginning of file
+        file.seek(io::SeekFrom::Start(0))?;
+
+        // Write the pattern over the entire file
+        let mut remaining = file_size;
+        while remaining > 0 {
+            let to_write = std::cmp::min(remaining, BUFFER_SIZE as u64) as usize;
+            file.write_all(&buffer[..to_write])?;
+            remaining -= to_write as u64;
+        }
+
+        // Flush to ensure data is written
+        file.flush()?;
+    }
+
+    // Final pass with random data
+    let mut rng = thread_rng();
+    for byte in buffer.iter_mut() {
+        *byte = rng.gen_range(0..=255);
+    }
+
+    file.seek(io::SeekFrom::Start(0))?;
+
+    let mut remaining = file_size;
+    while remaining > 0 {
+        let to_write = std::cmp::min(remaining, BUFFER_SIZE as u64) as usize;
+        file.write_all(&buffer[..to_write])?;
+        remaining -= to_write as u64;
+    }
+
+    file.flush()?;
+
+    // Close the file
+    drop(file);
+
+    // Now delete the file
+    fs::remove_file(path)?;
+
+    observer.event(&CopyEvent::Notice { message: &format!("Securely deleted file: {}", path.display()) });
+
+    Ok(())
+}
+
+pub(crate) fn secure_remove_dir_all(dir: &Path, observer: &dyn CopyObserver) -> io::Result<()> {
+    // First, recursively shred all files in subdirectories
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if path.is_dir() {
+                secure_remove_dir_all(&path, observer)?;
+            } else {
+                securely_delete_file(&path, observer)?;
+            }
+        }
+
+        // Remove the now-empty directory
+        fs::remove_dir(dir)?;
+        observer.event(&CopyEvent::Notice {
+            message: &format!("Removed directory after secure file deletion: {}", dir.display()),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::class::FileClass;
+
+/// Counters collected while a copy job runs, returned by [`CopyJob::run`](crate::CopyJob::run)
+///
+/// In list-only mode (/L) the counters tell what the job would have done.
+#[derive(Debug, Default, Clone)]
+pub struct Statistics {
+    pub dirs_created: usize,
+    pub files_copied: usize,
+    /// Logical size of the copied files
+    pub bytes_copied: u64,
+    /// Bytes actually written to the destination, less than `bytes_copied` when holes were skipped
+    /// or data was shared with a reflink
+    pub bytes_written: u64,
+    pub dirs_skipped: usize,
+    pub files_skipped: usize,
+    pub files_failed: usize,
+    pub dirs_failed: usize,
+    pub dirs_removed: usize,
+    pub files_removed: usize,
+    pub files_extra: usize,
+    pub dirs_extra: usize,
+    /// Source files by class, whether they were copied or not; extras are counted above
+    pub files_lonely: usize,
+    pub files_newer: usize,
+    pub files_older: usize,
+    pub files_changed: usize,
+    pub files_same: usize,
+    pub files_tweaked: usize,
+    pub mismatches: usize,
+    /// Copies that differed from the source when /V read them back, each one is copied again
+    pub files_verify_failed: usize,
+    /// Files of the same size that /CHECKSUM compared by hashing both copies
+    pub files_checksummed: usize,
+    /// Bytes read to compute those hashes, source and destination together
+    pub bytes_checksummed: u64,
+    /// Files moved with a rename instead of being copied
+    pub files_moved: usize,
+    /// Directories moved as a whole with a rename
+    pub dirs_moved: usize,
+    /// Destination files moved to the /BACKUPDIR before being deleted or overwritten
+    pub files_backed_up: usize,
+    /// One line per file or directory that could not be processed
+    pub failures: Vec<String>,
+    /// The job was cancelled before it finished
+    pub interrupted: bool,
+}
+
+// Exit code bits, compatible with Robocopy
+pub const EXIT_FILES_COPIED: u8 = 1;
+pub const EXIT_EXTRAS: u8 = 2;
+pub const EXIT_MISMATCHES: u8 = 4;
+pub const EXIT_FAILURES: u8 = 8;
+pub const EXIT_FATAL: u8 = 16;
+
+/// Exit code of a cancelled job, the same a shell reports for a process stopped by Ctrl-C
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+impl Statistics {
+    /// Count a source file in the counter of its class
+    pub(crate) fn count_class(&mut self, class: FileClass) {
+        match class {
+            FileClass::Lonely => self.files_lonely += 1,
+            FileClass::Newer => self.files_newer += 1,
+            FileClass::Older => self.files_older += 1,
+            FileClass::Changed => self.files_changed += 1,
+            FileClass::Same => self.files_same += 1,
+            FileClass::Tweaked => self.files_tweaked += 1,
+            FileClass::Extra => self.files_extra += 1,
+        }
+    }
+
+    /// Robocopy style exit code summarizing what happened during the run
+    pub fn exit_code(&self) -> u8 {
+        if self.interrupted {
+            return EXIT_INTERRUPTED;
+        }
+
+        let mut code = 0;
+
+        if self.files_copied > 0 || self.files_moved > 0 || self.dirs_moved > 0 {
+            code |= EXIT_FILES_COPIED;
+        }
+
+        if self.files_extra > 0 || self.dirs_extra > 0 || self.files_removed > 0 || self.dirs_removed > 0 {
+            code |= EXIT_EXTRAS;
+        }
+
+        if self.mismatches > 0 {
+            code |= EXIT_MISMATCHES;
+        }
+
+        if self.files_failed > 0 || self.dirs_failed > 0 {
+            code |= EXIT_FAILURES;
+        }
+
+        code
+    }
+}
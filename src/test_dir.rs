@@ -0,0 +1,49 @@
+//! Scratch directories for tests that need real files
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// An empty directory in the system's temporary directory, deleted with everything in it when dropped
+pub(crate) struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    pub(crate) fn new() -> Self {
+        // Tests run in parallel, and several test binaries may run at once
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("colemen_copy_test_{}_{}", process::id(), id));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+
+    /// `relative` inside the directory
+    pub(crate) fn join(&self, relative: &str) -> PathBuf {
+        self.path.join(relative)
+    }
+
+    /// Create files and, for paths ending in `/`, empty directories, along with their parents
+    pub(crate) fn create(&self, paths: &[&str]) {
+        for relative in paths {
+            let path = self.join(relative);
+            if relative.ends_with('/') {
+                fs::create_dir_all(&path).unwrap();
+            } else {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, relative.as_bytes()).unwrap();
+            }
+        }
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}